@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use azalea::{prelude::*, swarm::SwarmBuilder, NoState};
+use azalea_viaversion::{ViaController, ViaVersionPlugin};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let (plugin, controller) = ViaVersionPlugin::builder("1.21.4").start().await?;
+
+    let swarm = SwarmBuilder::new()
+        .add_account(Account::offline("Azalea"))
+        .add_plugins(plugin)
+        .set_handler(handler);
+
+    tokio::select! {
+        result = swarm.start("localhost") => {
+            result?;
+        }
+        result = lifecycle(controller) => result?,
+    }
+
+    Ok(())
+}
+
+/// Switch to another version after a while, then stop ViaProxy cleanly on Ctrl-C.
+async fn lifecycle(controller: ViaController) -> Result<()> {
+    tokio::time::sleep(Duration::from_secs(30)).await;
+
+    println!("Switching to 1.20.4, the bot will be kicked and rejoin");
+    controller.restart_with_version("1.20.4").await?;
+
+    tokio::signal::ctrl_c().await?;
+
+    let status = controller.stop().await?;
+    println!("ViaProxy stopped with {status:?}");
+
+    Ok(())
+}
+
+async fn handler(_client: Client, event: Event, _: NoState) -> Result<()> {
+    if let Event::Chat(chat) = event {
+        println!("{}", chat.message().to_ansi());
+    }
+
+    Ok(())
+}
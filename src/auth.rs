@@ -0,0 +1,347 @@
+use azalea::{
+    auth::sessionserver::{join_with_server_id_hash, ClientSessionServerError},
+    ecs::prelude::*,
+    Account,
+};
+use futures_util::FutureExt;
+use reqwest::Client;
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+use tokio::{sync::watch, task::JoinHandle};
+use tracing::{error, warn};
+
+use crate::{metrics, ViaOAuthFailed, ViaVersionPlugin};
+
+/// Why a bot's OpenAuthMod join failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthError {
+    /// Mojang rejected the session, even after refreshing the account.
+    InvalidSession,
+    /// The account isn't allowed to join servers, like when it's banned.
+    Forbidden(String),
+    /// Mojang's session server couldn't be reached or rate limited us, which is usually temporary.
+    Unavailable(String),
+    /// Refreshing the account's session failed.
+    RefreshFailed(String),
+    /// Mojang didn't respond in time.
+    TimedOut(Duration),
+    /// The server is online-mode, but the account with this name is offline, so it can't join.
+    OfflineAccount(String),
+    /// The session server responded in a way that isn't understood.
+    Other(String),
+}
+
+impl AuthError {
+    /// Whether trying again later might succeed.
+    #[must_use]
+    pub const fn is_transient(&self) -> bool {
+        matches!(self, Self::Unavailable(_) | Self::TimedOut(_))
+    }
+}
+
+impl From<ClientSessionServerError> for AuthError {
+    fn from(error: ClientSessionServerError) -> Self {
+        use ClientSessionServerError as E;
+
+        match error {
+            E::InvalidSession | E::ForbiddenOperation => Self::InvalidSession,
+            E::Banned | E::MultiplayerDisabled => Self::Forbidden(error.to_string()),
+            E::HttpError(_) | E::AuthServersUnreachable | E::RateLimited => {
+                Self::Unavailable(error.to_string())
+            }
+            E::UnexpectedResponse { status_code, .. } if status_code >= 500 => {
+                Self::Unavailable(error.to_string())
+            }
+            _ => Self::Other(error.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSession => write!(f, "Invalid session, even after refreshing the account"),
+            Self::Forbidden(error) => write!(f, "Not allowed to join: {error}"),
+            Self::Unavailable(error) => write!(f, "Session server unavailable: {error}"),
+            Self::RefreshFailed(error) => write!(f, "Failed to refresh account: {error}"),
+            Self::TimedOut(timeout) => write!(f, "Mojang didn't respond within {timeout:?}"),
+            Self::OfflineAccount(username) => write!(
+                f,
+                "Server is online-mode, but {username} is an offline account, use an online account or an offline-mode server"
+            ),
+            Self::Other(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// How long each request to Mojang may take, and how often a transient [`AuthError`] is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AuthPolicy {
+    pub(crate) attempts: u32,
+    pub(crate) delay: Duration,
+    pub(crate) timeout: Duration,
+}
+
+/// A bot's OpenAuthMod join that is still talking to Mojang.
+#[derive(Component)]
+pub struct OpenAuthModJoinTask(JoinHandle<Result<(), AuthError>>);
+
+/// The outcome of a bot's latest OpenAuthMod join.
+#[derive(Clone, Debug, Component)]
+pub struct OpenAuthModAuthResult(pub Result<(), AuthError>);
+
+impl OpenAuthModJoinTask {
+    pub(crate) const fn new(handle: JoinHandle<Result<(), AuthError>>) -> Self {
+        Self(handle)
+    }
+}
+
+/// Counts the OpenAuthMod joins of every bot that are still talking to Mojang.
+#[derive(Debug)]
+pub(crate) struct PendingAuthTasks(watch::Sender<usize>);
+
+impl Default for PendingAuthTasks {
+    fn default() -> Self {
+        Self(watch::Sender::new(0))
+    }
+}
+
+impl PendingAuthTasks {
+    pub(crate) fn count(&self) -> usize {
+        *self.0.borrow()
+    }
+
+    /// Count a join until the returned guard is dropped, even if its task is cancelled.
+    pub(crate) fn start(self: &Arc<Self>) -> PendingAuthTask {
+        self.0.send_modify(|count| *count += 1);
+        PendingAuthTask(self.clone())
+    }
+
+    /// Wait until every join has finished.
+    pub(crate) async fn drained(&self) {
+        /* The sender is borrowed, so this can't fail */
+        let _ = self.0.subscribe().wait_for(|&count| count == 0).await;
+    }
+}
+
+pub(crate) struct PendingAuthTask(Arc<PendingAuthTasks>);
+
+impl Drop for PendingAuthTask {
+    fn drop(&mut self) {
+        self.0 .0.send_modify(|count| *count -= 1);
+    }
+}
+
+pub(crate) type AuthCompleteHook = Arc<dyn Fn(Entity, Result<(), AuthError>) + Send + Sync>;
+
+/// Turn every finished [`OpenAuthModJoinTask`] into an [`OpenAuthModAuthResult`].
+///
+/// This also calls the plugin's
+/// [`on_auth_complete`](crate::ViaVersionBuilder::on_auth_complete) hook, if it has one, and sends
+/// a [`ViaOAuthFailed`] for joins that failed.
+#[allow(clippy::needless_pass_by_value)]
+pub fn poll_all_oam_join_tasks(
+    mut commands: Commands,
+    plugin: Option<Res<ViaVersionPlugin>>,
+    mut query: Query<(Entity, &mut OpenAuthModJoinTask)>,
+    mut failures: EventWriter<ViaOAuthFailed>,
+) {
+    for (entity, mut task) in &mut query {
+        let Some(result) = (&mut task.0).now_or_never() else {
+            continue;
+        };
+
+        let result = result.unwrap_or_else(|error| Err(AuthError::Other(error.to_string())));
+        metrics::record_auth(result.is_ok());
+        if let Err(error) = &result {
+            error!("Failed to do Mojang auth: {error}");
+            failures.send(ViaOAuthFailed {
+                entity,
+                error: error.clone(),
+            });
+        }
+
+        if let Some(hook) = plugin.as_ref().and_then(|plugin| plugin.on_auth_complete()) {
+            hook(entity, result.clone());
+        }
+
+        commands
+            .entity(entity)
+            .remove::<OpenAuthModJoinTask>()
+            .insert(OpenAuthModAuthResult(result));
+    }
+}
+
+/// Join the server with Mojang's session server, refreshing the account if its session expired.
+pub(crate) async fn authenticate(
+    client: &Client,
+    account: &Account,
+    server_id_hash: &str,
+    policy: AuthPolicy,
+) -> Result<(), AuthError> {
+    with_retries(policy, || {
+        join(client, account, server_id_hash, policy.timeout)
+    })
+    .await
+}
+
+/// Run `attempt` until it succeeds, fails permanently, or runs out of retries.
+async fn with_retries<F, Fut>(policy: AuthPolicy, mut attempt: F) -> Result<(), AuthError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), AuthError>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Err(error) if error.is_transient() && retries < policy.attempts => {
+                retries += 1;
+                warn!("{error}, retrying in {:?}", policy.delay);
+                tokio::time::sleep(policy.delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn join(
+    client: &Client,
+    account: &Account,
+    server_id_hash: &str,
+    timeout: Duration,
+) -> Result<(), AuthError> {
+    let uuid = account.uuid_or_offline();
+    let token = access_token(account)?;
+
+    let request = join_with_server_id_hash(client, &token, &uuid, server_id_hash);
+    match with_timeout(timeout, async { Ok(request.await) }).await? {
+        Err(
+            ClientSessionServerError::InvalidSession | ClientSessionServerError::ForbiddenOperation,
+        ) => {
+            let refresh = async {
+                account
+                    .refresh()
+                    .await
+                    .map_err(|error| AuthError::RefreshFailed(error.to_string()))
+            };
+            with_timeout(timeout, refresh).await?;
+
+            /* Retry with the refreshed token */
+            let token = access_token(account)?;
+            let request = async {
+                Ok(join_with_server_id_hash(client, &token, &uuid, server_id_hash).await?)
+            };
+            with_timeout(timeout, request).await
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Fail with [`AuthError::TimedOut`] if `future` takes longer than `timeout`.
+async fn with_timeout<T>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, AuthError>>,
+) -> Result<T, AuthError> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| AuthError::TimedOut(timeout))?
+}
+
+fn access_token(account: &Account) -> Result<String, AuthError> {
+    let access_token = account
+        .access_token
+        .as_ref()
+        .ok_or(AuthError::InvalidSession)?;
+
+    /* This is a parking_lot mutex, which can't be poisoned by a panicking holder */
+    Ok(access_token.lock().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_error_categories() {
+        let error = AuthError::from(ClientSessionServerError::RateLimited);
+        assert!(error.is_transient());
+
+        let error = AuthError::from(ClientSessionServerError::Banned);
+        assert!(matches!(error, AuthError::Forbidden(_)));
+        assert!(!error.is_transient());
+
+        let error = AuthError::from(ClientSessionServerError::UnexpectedResponse {
+            status_code: 503,
+            body: String::new(),
+        });
+        assert!(error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_pending_auth_tasks_drained() {
+        let tasks = Arc::new(PendingAuthTasks::default());
+        tasks.drained().await;
+
+        let task = tasks.start();
+        let other_task = tasks.start();
+        assert_eq!(tasks.count(), 2);
+
+        drop(task);
+        let drained = tasks.drained();
+        drop(other_task);
+        drained.await;
+        assert_eq!(tasks.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_never_resolves() {
+        let timeout = Duration::from_millis(10);
+        let refresh = std::future::pending::<Result<(), AuthError>>();
+        let result = with_timeout(timeout, refresh).await;
+        assert_eq!(result, Err(AuthError::TimedOut(timeout)));
+    }
+
+    #[tokio::test]
+    async fn test_with_retries_transient() {
+        let policy = AuthPolicy {
+            attempts: 2,
+            delay: Duration::ZERO,
+            timeout: Duration::MAX,
+        };
+
+        let mut calls = 0;
+        let result = with_retries(policy, || {
+            calls += 1;
+            let result = if calls < 3 {
+                Err(AuthError::Unavailable("down".to_owned()))
+            } else {
+                Ok(())
+            };
+            async move { result }
+        })
+        .await;
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(calls, 3);
+    }
+
+    #[tokio::test]
+    async fn test_with_retries_permanent() {
+        let policy = AuthPolicy {
+            attempts: 2,
+            delay: Duration::ZERO,
+            timeout: Duration::MAX,
+        };
+
+        let mut calls = 0;
+        let result = with_retries(policy, || {
+            calls += 1;
+            async { Err(AuthError::InvalidSession) }
+        })
+        .await;
+
+        assert_eq!(result, Err(AuthError::InvalidSession));
+        assert_eq!(calls, 1);
+    }
+}
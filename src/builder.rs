@@ -0,0 +1,1408 @@
+use anyhow::{bail, Context, Result};
+use azalea::{ecs::entity::Entity, Account};
+use futures_util::future;
+use reqwest::Client;
+use semver::Version;
+use std::{
+    collections::{hash_map::RandomState, HashSet},
+    env::consts::ARCH,
+    ffi::OsString,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{process::Command, runtime::Handle};
+use tracing::{debug, warn};
+
+use crate::{
+    auth::{AuthCompleteHook, AuthPolicy, PendingAuthTasks},
+    download::{download_file, DownloadOptions},
+    find_java,
+    output::STRUCTURED_LOG_CONFIG,
+    plugins::PluginSource,
+    process::{SpawnConfig, ViaProxyProcess},
+    progress::DownloadProgress,
+    proxy::BackendProxy,
+    reserve_addr,
+    versions::check_version,
+    warm::{self, WarmKey},
+    AuthError, ViaController, ViaVersionPlugin, JAVA_DOWNLOAD_URL, VIA_OAUTH_VERSION,
+    VIA_PROXY_VERSION,
+};
+
+/// Tokio's default [`BufReader`](tokio::io::BufReader) capacity.
+const DEFAULT_STDOUT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// How long a freshly spawned ViaProxy may stay silent before it's considered stuck.
+const DEFAULT_NO_OUTPUT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long ViaProxy may take to be ready after being spawned, which includes loading mappings on
+/// slow machines.
+const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How many times ViaProxy is moved to another port if its port is taken before it binds.
+const DEFAULT_BIND_RETRIES: u32 = 2;
+
+/// How many of ViaProxy's last lines are attached to errors when it fails to start.
+const DEFAULT_OUTPUT_CONTEXT_LINES: usize = 50;
+
+/// How long each request to Mojang's session server may take.
+const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+pub(crate) type PreSpawnHook = Arc<dyn Fn(&mut Command) + Send + Sync>;
+
+/// How ViaProxy authenticates bots with online-mode servers.
+///
+/// | Account \ Server | Offline-mode      | Online-mode                                  |
+/// |------------------|-------------------|----------------------------------------------|
+/// | Offline          | Any method works  | Not possible                                 |
+/// | Online           | Any method works  | [`AuthMethod::Auto`] or [`AuthMethod::OpenAuthMod`] |
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Use OpenAuthMod, but fall back to [`AuthMethod::None`] if it fails to download.
+    #[default]
+    Auto,
+    /// Use OpenAuthMod, failing to start if it can't be downloaded.
+    OpenAuthMod,
+    /// Don't authenticate, which only works with offline-mode servers.
+    None,
+}
+
+/// Which IP version ViaProxy listens on, and so which loopback address bots connect to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Pick for us, which is currently the same as [`AddressFamily::V4`].
+    #[default]
+    Auto,
+    /// Listen on `127.0.0.1`.
+    V4,
+    /// Listen on `::1`.
+    V6,
+}
+
+impl AddressFamily {
+    /// The loopback address of this family.
+    #[must_use]
+    pub const fn loopback(self) -> IpAddr {
+        match self {
+            Self::Auto | Self::V4 => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            Self::V6 => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        }
+    }
+}
+
+/// The point in ViaProxy's startup after which it's considered ready for bots.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadyMilestone {
+    /// ViaProxy is listening, but the first bots may wait for the mappings to finish loading.
+    ///
+    /// ViaProxy says it's binding before it does, so this waits a moment for a bind failure
+    /// before it's ready.
+    ProxyBound,
+    /// ViaVersion finished loading its mappings, so bots are translated right away.
+    #[default]
+    MappingsLoaded,
+}
+
+impl ReadyMilestone {
+    /// The text ViaProxy prints when it reaches this milestone.
+    pub(crate) const fn marker(self) -> &'static str {
+        match self {
+            Self::ProxyBound => "Binding proxy server to",
+            Self::MappingsLoaded => "Finished mapping loading",
+        }
+    }
+
+    /// Whether the marker is printed before ViaProxy binds its port, so a bind failure can still
+    /// follow it.
+    pub(crate) const fn precedes_bind(self) -> bool {
+        matches!(self, Self::ProxyBound)
+    }
+}
+
+/// How ViaProxy reads the target server from the host bots send, its `--wildcard-domain-handling`.
+///
+/// The plugin always rewrites the swarm's address into hosts like
+/// `127.0.0.1\x07example.com\x071.8`, which only [`WildcardDomainHandling::Internal`] reads.
+/// With another mode, bots have to join with their own [`JoinOpts`](azalea::JoinOpts) and a
+/// host in that mode's format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WildcardDomainHandling {
+    /// Connect to the server set in ViaProxy's config, ignoring the host.
+    None,
+    /// Read the target from a `.viaproxy.` subdomain of the host, for public proxies.
+    Public,
+    /// Read hosts split by `\x07`, which is what the plugin sends.
+    #[default]
+    Internal,
+}
+
+impl WildcardDomainHandling {
+    /// The name ViaProxy's `--wildcard-domain-handling` takes.
+    pub(crate) const fn as_arg(self) -> &'static str {
+        match self {
+            Self::None => "NONE",
+            Self::Public => "PUBLIC",
+            Self::Internal => "INTERNAL",
+        }
+    }
+}
+
+impl FromStr for WildcardDomainHandling {
+    type Err = anyhow::Error;
+
+    /// Parse one of ViaProxy's names for the mode, ignoring case.
+    fn from_str(mode: &str) -> Result<Self> {
+        [Self::None, Self::Public, Self::Internal]
+            .into_iter()
+            .find(|handling| handling.as_arg().eq_ignore_ascii_case(mode))
+            .with_context(|| {
+                format!(
+                    "Unknown wildcard domain handling {mode:?}, expected NONE, PUBLIC or INTERNAL"
+                )
+            })
+    }
+}
+
+/// Configure how ViaProxy is downloaded and started.
+///
+/// Created with [`ViaVersionPlugin::builder`].
+#[derive(Clone)]
+pub struct ViaVersionBuilder {
+    mc_version: String,
+    client: Option<Client>,
+    pre_spawn: Option<PreSpawnHook>,
+    auth_method: AuthMethod,
+    proxy_online_mode: bool,
+    auth_account: Option<Account>,
+    auth_policy: AuthPolicy,
+    require_auth: bool,
+    on_auth_complete: Option<AuthCompleteHook>,
+    persistent: bool,
+    working_dir: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    java_path: Option<PathBuf>,
+    jvm_args: Vec<String>,
+    via_proxy_version: Version,
+    via_proxy_jar: Option<PathBuf>,
+    via_oauth_jar: Option<PathBuf>,
+    via_proxy_sha256: Option<String>,
+    via_oauth_sha256: Option<String>,
+    config_file: Option<PathBuf>,
+    host_literal: Option<String>,
+    resolve_targets: bool,
+    via_debug: bool,
+    structured_logs: bool,
+    ready_milestone: ReadyMilestone,
+    wildcard_domain_handling: WildcardDomainHandling,
+    compression_threshold: Option<i32>,
+    legacy_skin_loading: Option<bool>,
+    extra_args: Vec<String>,
+    stdout_buffer_size: usize,
+    no_output_timeout: Duration,
+    startup_timeout: Duration,
+    output_context_lines: usize,
+    log_file: Option<PathBuf>,
+    log_file_max_size: Option<u64>,
+    spawn_jitter: Duration,
+    bind_retries: u32,
+    auto_restart: bool,
+    envs: Vec<(OsString, OsString)>,
+    clear_env: bool,
+    runtime: Option<Handle>,
+    download: DownloadOptions,
+    bind_host: Option<IpAddr>,
+    bind_addr: Option<SocketAddr>,
+    address_family: AddressFamily,
+    connect_host: Option<IpAddr>,
+    warm_reuse: bool,
+    backend_proxy: Option<BackendProxy>,
+    extra_plugins: Vec<PluginSource>,
+}
+
+impl fmt::Debug for ViaVersionBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ViaVersionBuilder")
+            .field("mc_version", &self.mc_version)
+            .field("client", &self.client)
+            .field("pre_spawn", &self.pre_spawn.is_some())
+            .field("persistent", &self.persistent)
+            .field("working_dir", &self.working_dir)
+            .field("data_dir", &self.data_dir)
+            .field("java_path", &self.java_path)
+            /* Only counts and names, since these are where credentials end up */
+            .field("jvm_args", &self.jvm_args.len())
+            .field("via_proxy_version", &self.via_proxy_version)
+            .field("via_proxy_jar", &self.via_proxy_jar)
+            .field("via_oauth_jar", &self.via_oauth_jar)
+            .field("via_proxy_sha256", &self.via_proxy_sha256)
+            .field("via_oauth_sha256", &self.via_oauth_sha256)
+            .field("config_file", &self.config_file)
+            .field("host_literal", &self.host_literal)
+            .field("resolve_targets", &self.resolve_targets)
+            .field("via_debug", &self.via_debug)
+            .field("structured_logs", &self.structured_logs)
+            .field("ready_milestone", &self.ready_milestone)
+            .field("wildcard_domain_handling", &self.wildcard_domain_handling)
+            .field("compression_threshold", &self.compression_threshold)
+            .field("legacy_skin_loading", &self.legacy_skin_loading)
+            .field("extra_args", &self.extra_args.len())
+            .field("stdout_buffer_size", &self.stdout_buffer_size)
+            .field("no_output_timeout", &self.no_output_timeout)
+            .field("startup_timeout", &self.startup_timeout)
+            .field("output_context_lines", &self.output_context_lines)
+            .field("log_file", &self.log_file)
+            .field("log_file_max_size", &self.log_file_max_size)
+            .field("spawn_jitter", &self.spawn_jitter)
+            .field("bind_retries", &self.bind_retries)
+            .field("auto_restart", &self.auto_restart)
+            .field(
+                "envs",
+                &self.envs.iter().map(|(key, _)| key).collect::<Vec<_>>(),
+            )
+            .field("clear_env", &self.clear_env)
+            .field("runtime", &self.runtime)
+            .field("bind_host", &self.bind_host)
+            .field("bind_addr", &self.bind_addr)
+            .field("address_family", &self.address_family)
+            .field("connect_host", &self.connect_host)
+            .field("warm_reuse", &self.warm_reuse)
+            .field("backend_proxy", &self.backend_proxy)
+            .field("extra_plugins", &self.extra_plugins)
+            .field("force_download", &self.download.force)
+            .field("offline", &self.download.offline)
+            .field("progress_bar", &self.download.progress_bar)
+            .field("download_retries", &self.download.retries)
+            .field(
+                "on_download_progress",
+                &self.download.progress_hook.is_some(),
+            )
+            .field("auth_method", &self.auth_method)
+            .field("proxy_online_mode", &self.proxy_online_mode)
+            .field(
+                "auth_account",
+                &self.auth_account.as_ref().map(|account| &account.username),
+            )
+            .field("auth_policy", &self.auth_policy)
+            .field("require_auth", &self.require_auth)
+            .field("on_auth_complete", &self.on_auth_complete.is_some())
+            .finish()
+    }
+}
+
+impl ViaVersionBuilder {
+    pub(crate) const fn new(mc_version: String) -> Self {
+        Self {
+            mc_version,
+            client: None,
+            pre_spawn: None,
+            auth_method: AuthMethod::Auto,
+            proxy_online_mode: false,
+            auth_account: None,
+            auth_policy: AuthPolicy {
+                attempts: 0,
+                delay: Duration::ZERO,
+                timeout: DEFAULT_AUTH_TIMEOUT,
+            },
+            require_auth: false,
+            on_auth_complete: None,
+            persistent: false,
+            working_dir: None,
+            data_dir: None,
+            java_path: None,
+            jvm_args: Vec::new(),
+            via_proxy_version: VIA_PROXY_VERSION,
+            via_proxy_jar: None,
+            via_oauth_jar: None,
+            via_proxy_sha256: None,
+            via_oauth_sha256: None,
+            config_file: None,
+            host_literal: None,
+            resolve_targets: false,
+            via_debug: false,
+            structured_logs: false,
+            ready_milestone: ReadyMilestone::MappingsLoaded,
+            wildcard_domain_handling: WildcardDomainHandling::Internal,
+            compression_threshold: None,
+            legacy_skin_loading: None,
+            extra_args: Vec::new(),
+            stdout_buffer_size: DEFAULT_STDOUT_BUFFER_SIZE,
+            no_output_timeout: DEFAULT_NO_OUTPUT_TIMEOUT,
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            output_context_lines: DEFAULT_OUTPUT_CONTEXT_LINES,
+            log_file: None,
+            log_file_max_size: None,
+            spawn_jitter: Duration::ZERO,
+            bind_retries: DEFAULT_BIND_RETRIES,
+            auto_restart: false,
+            envs: Vec::new(),
+            clear_env: false,
+            runtime: None,
+            download: DownloadOptions::new(),
+            bind_host: None,
+            bind_addr: None,
+            address_family: AddressFamily::Auto,
+            connect_host: None,
+            warm_reuse: false,
+            backend_proxy: None,
+            extra_plugins: Vec::new(),
+        }
+    }
+
+    /// Use a custom HTTP client for the jar downloads and the sessionserver requests.
+    ///
+    /// This is useful for custom root certificates, proxies, or user-agents.
+    /// By default a client with this crate's user-agent is used.
+    #[must_use]
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Set how ViaProxy authenticates bots with online-mode servers.
+    ///
+    /// Defaults to [`AuthMethod::Auto`].
+    #[must_use]
+    pub const fn with_auth_method(mut self, auth_method: AuthMethod) -> Self {
+        self.auth_method = auth_method;
+        self
+    }
+
+    /// Make ViaProxy itself authenticate bots that connect to it, like an online-mode server.
+    ///
+    /// This is ViaProxy's `--proxy-online-mode`, which lets it forward skins and signed chat, but
+    /// it only works with online accounts. Defaults to `false`.
+    #[must_use]
+    pub const fn with_proxy_online_mode(mut self, proxy_online_mode: bool) -> Self {
+        self.proxy_online_mode = proxy_online_mode;
+        self
+    }
+
+    /// Authenticate every bot's OpenAuthMod join with this account, instead of the bot's own.
+    ///
+    /// This lets one licensed account authenticate a fleet of offline bots, but only where the
+    /// server permits it. Sharing one session between many connections may break the server's
+    /// rules or Mojang's terms of service, and gets every bot banned together.
+    #[must_use]
+    pub fn with_auth_account(mut self, account: Account) -> Self {
+        self.auth_account = Some(account);
+        self
+    }
+
+    /// Fail an offline account's OpenAuthMod join right away, instead of only logging it.
+    ///
+    /// The join is answered as failed and the bot gets an
+    /// [`AuthError::OfflineAccount`](crate::AuthError::OfflineAccount) naming the account in its
+    /// [`OpenAuthModAuthResult`](crate::OpenAuthModAuthResult), rather than the server later
+    /// kicking it with a vague message about authentication. Defaults to `false`.
+    #[must_use]
+    pub const fn with_require_auth(mut self, require_auth: bool) -> Self {
+        self.require_auth = require_auth;
+        self
+    }
+
+    /// Make ViaProxy connect to servers through a SOCKS5 proxy, or an HTTP proxy with
+    /// [`BackendProxy::http`]. Starting fails if an HTTP proxy's URL isn't `http://`.
+    ///
+    /// This is ViaProxy's `--backend-proxy-url`. Bots connect to ViaProxy directly, so don't also
+    /// give them a proxy in their [`JoinOpts`](azalea::JoinOpts).
+    #[must_use]
+    pub fn with_backend_proxy(mut self, proxy: impl Into<BackendProxy>) -> Self {
+        self.backend_proxy = Some(proxy.into());
+        self
+    }
+
+    /// Set the interface ViaProxy listens on, like `0.0.0.0` to make it reachable from other
+    /// containers.
+    ///
+    /// Defaults to the loopback address of the [address family](Self::with_address_family), like
+    /// `127.0.0.1`. Bots keep connecting over loopback unless [`Self::with_connect_host`] is also
+    /// set.
+    #[must_use]
+    pub const fn with_bind_host(mut self, bind_host: IpAddr) -> Self {
+        self.bind_host = Some(bind_host);
+        self
+    }
+
+    /// Set the exact address and port ViaProxy listens on, for firewall rules that need a fixed
+    /// port.
+    ///
+    /// This replaces [`Self::with_bind_host`], and the port isn't moved when it's taken, so
+    /// [`Self::start`] fails with [`ViaStartError::PortInUse`](crate::ViaStartError::PortInUse)
+    /// instead. Defaults to a free port picked by the system.
+    #[must_use]
+    pub const fn with_bind_addr(mut self, bind_addr: SocketAddr) -> Self {
+        self.bind_addr = Some(bind_addr);
+        self
+    }
+
+    /// Set which IP version ViaProxy listens on over loopback, and so which address bots connect
+    /// to and which literal is written into their hosts.
+    ///
+    /// Defaults to [`AddressFamily::Auto`]. This is ignored if [`Self::with_bind_host`] or
+    /// [`Self::with_bind_addr`] is set.
+    #[must_use]
+    pub const fn with_address_family(mut self, address_family: AddressFamily) -> Self {
+        self.address_family = address_family;
+        self
+    }
+
+    /// Set the address bots connect to ViaProxy at.
+    ///
+    /// Defaults to the bind host, or loopback if ViaProxy listens on every interface.
+    #[must_use]
+    pub const fn with_connect_host(mut self, connect_host: IpAddr) -> Self {
+        self.connect_host = Some(connect_host);
+        self
+    }
+
+    /// Reuse the ViaProxy started by an earlier [`Self::start`] in this process, if it was started
+    /// with the same settings and is still running.
+    ///
+    /// This is meant for dev loops and tests that start the plugin many times, and keeps the last
+    /// proxy running until the process exits. A proxy started with other settings replaces it.
+    /// The [`Self::pre_spawn`] hook and [`Self::with_runtime`] aren't compared. Defaults to
+    /// `false`.
+    #[must_use]
+    pub const fn with_warm_reuse(mut self, warm_reuse: bool) -> Self {
+        self.warm_reuse = warm_reuse;
+        self
+    }
+
+    /// Retry OpenAuthMod joins that failed for a temporary reason, like a Mojang outage.
+    ///
+    /// A join that keeps failing with a [transient](crate::AuthError::is_transient) error is tried
+    /// `attempts` more times, waiting `delay` in between. Permanent failures, like an
+    /// invalid or banned account, are never retried. Defaults to no retries.
+    #[must_use]
+    pub const fn with_auth_retries(mut self, attempts: u32, delay: Duration) -> Self {
+        self.auth_policy.attempts = attempts;
+        self.auth_policy.delay = delay;
+        self
+    }
+
+    /// Give up on each request to Mojang's session server, including refreshing the account, if
+    /// it takes longer than this.
+    ///
+    /// A join that times out fails with [`AuthError::TimedOut`](crate::AuthError::TimedOut),
+    /// which is retried like other temporary failures. Defaults to 30 seconds.
+    #[must_use]
+    pub const fn with_auth_timeout(mut self, timeout: Duration) -> Self {
+        self.auth_policy.timeout = timeout;
+        self
+    }
+
+    /// Keep ViaProxy running after the plugin and its controllers are dropped, or the app exits.
+    ///
+    /// By default ViaProxy is killed when the app sends [`AppExit`](azalea::app::AppExit), and when
+    /// the child is dropped as a fallback. A persistent proxy is started in its own
+    /// process group so it also survives Ctrl-C, which is useful for reconnecting to the same
+    /// instance while debugging. It can still be stopped with [`ViaController::shutdown`].
+    #[must_use]
+    pub const fn with_persistent_proxy(mut self, persistent: bool) -> Self {
+        self.persistent = persistent;
+        self
+    }
+
+    /// Run ViaProxy in this directory, where it writes its config, logs, and loads plugins from.
+    ///
+    /// Defaults to the folder the jars are downloaded to.
+    #[must_use]
+    pub fn with_working_dir(mut self, working_dir: impl Into<PathBuf>) -> Self {
+        self.working_dir = Some(working_dir.into());
+        self
+    }
+
+    /// Keep the `azalea-viaversion` folder the jars are downloaded to in this directory, instead of
+    /// the Minecraft folder.
+    ///
+    /// This is needed where there's no Minecraft folder, like in containers without a home
+    /// directory, and keeps instances that shouldn't share files apart. It's also where ViaProxy
+    /// runs unless [`Self::with_working_dir`] is set.
+    #[must_use]
+    pub fn with_data_dir(mut self, data_dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(data_dir.into());
+        self
+    }
+
+    /// Run ViaProxy with this `java`, for systems without Java on `PATH` or with several JDKs.
+    ///
+    /// Its version picks which ViaProxy jar is downloaded. Defaults to `java` on `PATH`, then
+    /// `$JAVA_HOME/bin/java` if that isn't found.
+    #[must_use]
+    pub fn with_java_path(mut self, java_path: impl Into<PathBuf>) -> Self {
+        self.java_path = Some(java_path.into());
+        self
+    }
+
+    /// Pass these arguments to the JVM, like `-Xmx2G` for a ViaProxy shared by many bots.
+    ///
+    /// They go before `-jar`, so they can't be ViaProxy's own arguments, and are added to the ones
+    /// from earlier calls.
+    #[must_use]
+    pub fn with_jvm_args(mut self, jvm_args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.jvm_args.extend(jvm_args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Download this ViaProxy release instead of the one the crate was tested with, for servers
+    /// that need a newer ViaProxy.
+    ///
+    /// The `+java8` build is still picked for Java versions before 17. Fails to start if the
+    /// release doesn't exist on GitHub. Defaults to the bundled version.
+    #[must_use]
+    pub fn with_via_proxy_version(mut self, via_proxy_version: Version) -> Self {
+        self.via_proxy_version = via_proxy_version;
+        self
+    }
+
+    /// Run this ViaProxy jar instead of downloading a release, like a local build or a patched one.
+    ///
+    /// The jar is used as is, so it has to suit the installed Java, while OpenAuthMod and extra
+    /// plugins are still downloaded unless they're also given as paths. Fails to start if the jar
+    /// doesn't exist or can't be read.
+    #[must_use]
+    pub fn with_via_proxy_jar(mut self, via_proxy_jar: impl Into<PathBuf>) -> Self {
+        self.via_proxy_jar = Some(via_proxy_jar.into());
+        self
+    }
+
+    /// Copy this ViaProxyOpenAuthMod jar into ViaProxy's `plugins` folder instead of downloading
+    /// it, for machines that can't reach GitHub.
+    ///
+    /// Fails to start if the jar doesn't exist or can't be read, even with [`AuthMethod::Auto`].
+    /// Unused with [`AuthMethod::None`].
+    #[must_use]
+    pub fn with_via_oauth_jar(mut self, via_oauth_jar: impl Into<PathBuf>) -> Self {
+        self.via_oauth_jar = Some(via_oauth_jar.into());
+        self
+    }
+
+    /// Start ViaProxy with this `viaproxy.yml`, for settings that don't have their own setter.
+    ///
+    /// The flags the plugin passes take precedence over the file. These are always the auth
+    /// method, bind address, target address and version, and wildcard domain handling, which the
+    /// plugin needs to route bots, plus any setting from this builder that was changed from its
+    /// default, like [`Self::with_compression_threshold`]. Everything else comes from the file.
+    #[must_use]
+    pub fn with_config_file(mut self, config_file: impl Into<PathBuf>) -> Self {
+        self.config_file = Some(config_file.into());
+        self
+    }
+
+    /// Override the first segment of the host bots send to ViaProxy.
+    ///
+    /// Defaults to the IP ViaProxy is bound to, like `127.0.0.1`, instead of `localhost`, which
+    /// may resolve to IPv6 first or be overridden in `/etc/hosts`.
+    #[must_use]
+    pub fn with_host_literal(mut self, host_literal: impl Into<String>) -> Self {
+        self.host_literal = Some(host_literal.into());
+        self
+    }
+
+    /// Give ViaProxy the IP azalea resolved the swarm's address to, instead of the host.
+    ///
+    /// By default ViaProxy resolves the host itself, out of reach of custom resolvers. With this,
+    /// the target is resolved by azalea, following SRV records and the system's resolver like a
+    /// direct connection would. Use [`ViaVersionPlugin::resolved_join_opts`] for bots joining
+    /// with their own [`JoinOpts`](azalea::JoinOpts). Defaults to `false`.
+    #[must_use]
+    pub const fn with_resolve_targets(mut self, resolve_targets: bool) -> Self {
+        self.resolve_targets = resolve_targets;
+        self
+    }
+
+    /// Turn on ViaVersion's debug logging and log ViaProxy's output at `debug` instead of `trace`.
+    ///
+    /// This is meant for diagnosing translation bugs and is very verbose, since it can log every
+    /// packet. Defaults to `false`.
+    #[must_use]
+    pub const fn with_via_debug(mut self, via_debug: bool) -> Self {
+        self.via_debug = via_debug;
+        self
+    }
+
+    /// Make ViaProxy print its logs as JSON, so the plugin reads each record's level, source and
+    /// message from their own fields instead of picking them out of a formatted line.
+    ///
+    /// This replaces ViaProxy's log4j2 config with one written to the working dir, so ViaProxy's
+    /// own log files aren't written. Lines that aren't JSON, like the JVM's own output, are still
+    /// parsed as before. Defaults to `false`.
+    #[must_use]
+    pub const fn with_structured_logs(mut self, structured_logs: bool) -> Self {
+        self.structured_logs = structured_logs;
+        self
+    }
+
+    /// Choose how ViaProxy reads the target server from the host bots send.
+    ///
+    /// Defaults to [`WildcardDomainHandling::Internal`], the only mode the plugin's own routing
+    /// works with, so changing it means routing bots yourself. See [`WildcardDomainHandling`].
+    #[must_use]
+    pub const fn with_wildcard_domain_handling(
+        mut self,
+        wildcard_domain_handling: WildcardDomainHandling,
+    ) -> Self {
+        self.wildcard_domain_handling = wildcard_domain_handling;
+        self
+    }
+
+    /// Choose which startup milestone [`ViaVersionBuilder::start`] waits for.
+    ///
+    /// Defaults to [`ReadyMilestone::MappingsLoaded`].
+    #[must_use]
+    pub const fn with_ready_milestone(mut self, ready_milestone: ReadyMilestone) -> Self {
+        self.ready_milestone = ready_milestone;
+        self
+    }
+
+    /// Set the packet size above which ViaProxy compresses packets sent to the bots, or `-1` to
+    /// not compress them at all.
+    ///
+    /// This is ViaProxy's `--compression-threshold` and only affects the local hop, where
+    /// compression costs CPU without saving any real bandwidth. The server's own threshold still
+    /// applies between ViaProxy and the server. Defaults to ViaProxy's setting.
+    #[must_use]
+    pub const fn with_compression_threshold(mut self, compression_threshold: i32) -> Self {
+        self.compression_threshold = Some(compression_threshold);
+        self
+    }
+
+    /// Set whether ViaProxy fetches skins from Mojang for servers older than 1.7.
+    ///
+    /// This is ViaProxy's `--legacy-skin-loading`. Those servers don't send skins themselves, so
+    /// ViaProxy looks up every player's profile, which adds Mojang API calls and latency for each
+    /// join. It doesn't change how bots are authenticated, but with
+    /// [`Self::with_proxy_online_mode`] the bots' own skins are already known from their sessions.
+    /// Defaults to ViaProxy's setting.
+    #[must_use]
+    pub const fn with_legacy_skin_loading(mut self, legacy_skin_loading: bool) -> Self {
+        self.legacy_skin_loading = Some(legacy_skin_loading);
+        self
+    }
+
+    /// Pass these arguments to ViaProxy, for its options without a builder method, like
+    /// `["--chat-signing", "false"]`.
+    ///
+    /// They're passed as is, after every argument the plugin manages, and are added to the ones
+    /// from earlier calls. Passing an option the plugin also sets usually overrides it, depending
+    /// on how ViaProxy parses it, but changing the bind address, target version or wildcard
+    /// domain handling breaks routing bots through it.
+    #[must_use]
+    pub fn with_extra_args(
+        mut self,
+        extra_args: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.extra_args
+            .extend(extra_args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Set how many bytes of ViaProxy's output are buffered per read.
+    ///
+    /// A bigger buffer keeps up better with very verbose output, like [`Self::with_via_debug`].
+    /// Defaults to 8 KiB.
+    #[must_use]
+    pub const fn with_stdout_buffer_size(mut self, stdout_buffer_size: usize) -> Self {
+        self.stdout_buffer_size = stdout_buffer_size;
+        self
+    }
+
+    /// Fail with [`ViaStartError::NoOutput`](crate::ViaStartError::NoOutput) if ViaProxy prints
+    /// nothing for this long after being spawned.
+    ///
+    /// Defaults to 30 seconds.
+    #[must_use]
+    pub const fn with_no_output_timeout(mut self, no_output_timeout: Duration) -> Self {
+        self.no_output_timeout = no_output_timeout;
+        self
+    }
+
+    /// Fail with [`ViaStartError::StartupTimeout`](crate::ViaStartError::StartupTimeout) if
+    /// ViaProxy isn't ready this long after being spawned, killing it.
+    ///
+    /// This is what [`ReadyMilestone`] waits for, so a stalled startup fails health checks instead
+    /// of blocking forever. Defaults to 120 seconds.
+    #[must_use]
+    pub const fn with_startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Wait a random time up to `spawn_jitter` before downloading and spawning ViaProxy.
+    ///
+    /// This spreads out the downloads and JVM startups when many instances start at once, like in
+    /// a fleet of processes that each run a swarm. Defaults to no delay.
+    #[must_use]
+    pub const fn with_spawn_jitter(mut self, spawn_jitter: Duration) -> Self {
+        self.spawn_jitter = spawn_jitter;
+        self
+    }
+
+    /// Set how many of ViaProxy's last lines of output are attached to the error when it fails to
+    /// start, so the error can be pasted into a bug report as is.
+    ///
+    /// Set to 0 to leave them out. Defaults to 50.
+    #[must_use]
+    pub const fn with_output_context_lines(mut self, output_context_lines: usize) -> Self {
+        self.output_context_lines = output_context_lines;
+        self
+    }
+
+    /// Also write all of ViaProxy's output to this file, for looking into kicks after the fact.
+    ///
+    /// Lines are still logged with `tracing` too, and are appended to the file across restarts.
+    /// Terminal colors are removed, and stderr lines start with `[stderr]`. See
+    /// [`Self::with_log_file_max_size`] to keep the file from growing forever.
+    #[must_use]
+    pub fn with_log_file(mut self, log_file: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(log_file.into());
+        self
+    }
+
+    /// Rotate the [log file](Self::with_log_file) once it would grow past this many bytes.
+    ///
+    /// The full file is moved to the same path with `.1` added, replacing the one from the last
+    /// rotation, so at most twice this much is kept. Defaults to never rotating.
+    #[must_use]
+    pub const fn with_log_file_max_size(mut self, max_size: u64) -> Self {
+        self.log_file_max_size = Some(max_size);
+        self
+    }
+
+    /// Set how many times ViaProxy is moved to another free port if something else takes its port
+    /// before it binds, which happens on busy machines that start many proxies at once.
+    ///
+    /// This also applies when a [`ViaController`] restarts ViaProxy. Fails with
+    /// [`ViaStartError::PortInUse`](crate::ViaStartError::PortInUse) once the retries run out.
+    /// Defaults to 2.
+    #[must_use]
+    pub const fn with_bind_retries(mut self, bind_retries: u32) -> Self {
+        self.bind_retries = bind_retries;
+        self
+    }
+
+    /// Start ViaProxy again whenever it exits on its own, like after a crash, instead of leaving
+    /// bots unable to connect until it's restarted through a [`ViaController`].
+    ///
+    /// ViaProxy is checked every second, and restarted with the same settings after a wait that
+    /// starts at a second and doubles up to a minute while it keeps exiting, so a ViaProxy that
+    /// can't start doesn't respawn in a tight loop. Each restart sends a
+    /// [`ViaProxyRestarted`](crate::ViaProxyRestarted) once it's ready. Stopping ViaProxy through
+    /// a controller doesn't count as exiting on its own. Defaults to `false`.
+    #[must_use]
+    pub const fn with_auto_restart(mut self, auto_restart: bool) -> Self {
+        self.auto_restart = auto_restart;
+        self
+    }
+
+    /// Spawn the plugin's background tasks on this tokio runtime.
+    ///
+    /// These are the output reader and the OpenAuthMod joins. By default they're spawned on the
+    /// runtime [`ViaVersionBuilder::start`] is called from, which must then still be running when
+    /// bots join. The runtime needs its IO and time drivers enabled, and ViaProxy's process must
+    /// be spawned from a runtime too, so `start` has to be awaited inside one either way.
+    #[must_use]
+    pub fn with_runtime(mut self, runtime: Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    /// Download the jars again even if they're already cached, to recover from a corrupt cache.
+    ///
+    /// The cached jars are only replaced once the new ones have fully downloaded, so a failed
+    /// download leaves them as they were. Defaults to `false`.
+    #[must_use]
+    pub const fn with_force_download(mut self, force: bool) -> Self {
+        self.download.force = force;
+        self
+    }
+
+    /// Set how many times a download is retried after a connection error, a timeout, a server
+    /// error, being rate limited, or being cut off, with the wait doubling from 500ms each time.
+    ///
+    /// Each retry downloads the file from scratch. Other client errors like a 404, and errors
+    /// saving the file, fail right away. Defaults to 4.
+    #[must_use]
+    pub const fn with_download_retries(mut self, retries: u32) -> Self {
+        self.download.retries = retries;
+        self
+    }
+
+    /// Never download anything, for machines that intentionally have no internet access.
+    ///
+    /// The jars have to be downloaded already, from an earlier start online, or be given with
+    /// [`Self::with_via_proxy_jar`] and [`Self::with_via_oauth_jar`]. A jar that's missing fails
+    /// with [`ViaStartError::NotDownloaded`](crate::ViaStartError::NotDownloaded) instead of a
+    /// network error, except OpenAuthMod with [`AuthMethod::Auto`], which is skipped with a
+    /// warning. This takes precedence over [`Self::with_force_download`]. Defaults to `false`.
+    #[must_use]
+    pub const fn with_offline(mut self, offline: bool) -> Self {
+        self.download.offline = offline;
+        self
+    }
+
+    /// Add another ViaProxy plugin, from an HTTP(S) URL or a path on disk.
+    ///
+    /// The jar is put in the `plugins` folder of the [working dir](Self::with_working_dir) before
+    /// ViaProxy starts. URLs are downloaded once and cached by their file name, so include the
+    /// version in it, while paths are copied again on every start.
+    #[must_use]
+    pub fn with_extra_plugin(mut self, url_or_path: impl AsRef<str>) -> Self {
+        self.extra_plugins
+            .push(PluginSource::parse(url_or_path.as_ref()));
+        self
+    }
+
+    /// Check the downloaded ViaProxy jar against this SHA-256 digest in hex.
+    ///
+    /// The digest is of the jar that gets picked for the installed Java, so it differs for the
+    /// `+java8` build. A cached jar that doesn't match is downloaded again, and a download that
+    /// doesn't match fails with [`ViaStartError::ChecksumMismatch`](crate::ViaStartError::ChecksumMismatch).
+    #[must_use]
+    pub fn with_via_proxy_sha256(mut self, sha256: impl AsRef<str>) -> Self {
+        self.via_proxy_sha256 = Some(sha256.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Check the downloaded ViaProxyOpenAuthMod jar against this SHA-256 digest in hex, like
+    /// [`Self::with_via_proxy_sha256`].
+    #[must_use]
+    pub fn with_via_oauth_sha256(mut self, sha256: impl AsRef<str>) -> Self {
+        self.via_oauth_sha256 = Some(sha256.as_ref().to_ascii_lowercase());
+        self
+    }
+
+    /// Choose whether downloads render a progress bar, instead of only rendering one when stderr
+    /// is a terminal.
+    ///
+    /// Without a bar, progress is logged every 10% instead. The bar needs the `progress` feature,
+    /// so this does nothing without it, since progress is always logged then.
+    #[must_use]
+    pub const fn with_progress(mut self, progress: bool) -> Self {
+        self.download.progress_bar = Some(progress);
+        self
+    }
+
+    /// Call a hook with the speed and ETA of the jar downloads, like for showing them in a UI.
+    ///
+    /// The hook is called from the download loop, so it should return quickly.
+    #[must_use]
+    pub fn on_download_progress<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&DownloadProgress) + Send + Sync + 'static,
+    {
+        self.download.progress_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Set an environment variable for ViaProxy, like `JAVA_TOOL_OPTIONS` or `LC_ALL`.
+    ///
+    /// This is added to the environment ViaProxy inherits from the app, unless
+    /// [`Self::with_clear_env`] is set. Setting the same key again overrides it.
+    #[must_use]
+    pub fn with_env(mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        let key = key.into();
+        self.envs.retain(|(existing, _)| *existing != key);
+        self.envs.push((key, value.into()));
+        self
+    }
+
+    /// Start ViaProxy with only the variables set with [`Self::with_env`], instead of adding them
+    /// to the app's environment.
+    ///
+    /// `java` is still found with the app's `PATH` and `JAVA_HOME`, and spawned by its absolute
+    /// path, but variables the JVM may need, like `JAVA_HOME` or `HOME`, have to be set again.
+    /// Defaults to `false`.
+    #[must_use]
+    pub const fn with_clear_env(mut self, clear_env: bool) -> Self {
+        self.clear_env = clear_env;
+        self
+    }
+
+    /// Call a hook with each bot's OpenAuthMod join result, as an alternative to watching for
+    /// [`OpenAuthModAuthResult`](crate::OpenAuthModAuthResult) in a system.
+    ///
+    /// The hook is called from the ECS update, so it should return quickly.
+    #[must_use]
+    pub fn on_auth_complete<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(Entity, Result<(), AuthError>) + Send + Sync + 'static,
+    {
+        self.on_auth_complete = Some(Arc::new(hook));
+        self
+    }
+
+    /// Run a hook on the ViaProxy [`Command`] right before it's spawned.
+    ///
+    /// This is an escape hatch for environment-specific needs like setting `JAVA_TOOL_OPTIONS`.
+    /// The hook runs after the plugin has added its own args, which shouldn't be removed.
+    #[must_use]
+    pub fn pre_spawn<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&mut Command) + Send + Sync + 'static,
+    {
+        self.pre_spawn = Some(Arc::new(hook));
+        self
+    }
+
+    /// Route bots through a ViaProxy that's already running on `bind_addr`, instead of downloading
+    /// and starting one, so several apps or plugins can share it.
+    ///
+    /// ViaProxy has to run with `--wildcard-domain-handling INTERNAL`, and with the OpenAuthMod
+    /// plugin and `--auth-method OPENAUTHMOD` for online-mode servers. The auth settings and the
+    /// address settings still apply, while the settings for spawning ViaProxy are ignored. The
+    /// returned [`ViaController`] can't restart it.
+    ///
+    /// # Errors
+    /// Will return `Err` if the default HTTP client fails to build.
+    pub fn connect_existing(
+        self,
+        bind_addr: SocketAddr,
+    ) -> Result<(ViaVersionPlugin, ViaController)> {
+        let client = match self.client {
+            Some(client) => client,
+            None => default_client()?,
+        };
+
+        let connect_host = self
+            .connect_host
+            .unwrap_or_else(|| default_connect_host(bind_addr.ip()));
+        let process = Arc::new(ViaProxyProcess::external(
+            bind_addr,
+            connect_host,
+            self.mc_version,
+        ));
+
+        let pending_auth_tasks = Arc::new(PendingAuthTasks::default());
+        let plugin = ViaVersionPlugin {
+            process: process.clone(),
+            client,
+            auth_account: self.auth_account,
+            auth_policy: self.auth_policy,
+            require_auth: self.require_auth,
+            on_auth_complete: self.on_auth_complete,
+            pending_auth_tasks: pending_auth_tasks.clone(),
+            host_literal: self.host_literal,
+            resolve_targets: self.resolve_targets,
+            route: Arc::default(),
+            runtime: self.runtime,
+        };
+
+        Ok((plugin, ViaController::new(process, pending_auth_tasks)))
+    }
+
+    /// Download and start a ViaProxy instance.
+    ///
+    /// The returned [`ViaController`] can stop or restart ViaProxy after the plugin has been added.
+    ///
+    /// # Errors
+    /// Will return `Err` if the bundled ViaProxy can't target the version, java fails to parse,
+    /// files fail to download, or ViaProxy fails to start.
+    pub async fn start(self) -> Result<(ViaVersionPlugin, ViaController)> {
+        /* Other ViaProxy versions may support versions the bundled table doesn't know */
+        if self.via_proxy_jar.is_none() && self.via_proxy_version == VIA_PROXY_VERSION {
+            check_version(&self.mc_version)?;
+        }
+
+        if let Some(backend_proxy) = &self.backend_proxy {
+            backend_proxy.validate()?;
+        }
+
+        if !self.spawn_jitter.is_zero() {
+            let delay = random_delay(self.spawn_jitter);
+            debug!("Waiting {delay:?} before starting ViaProxy");
+            tokio::time::sleep(delay).await;
+        }
+
+        let started_at = Instant::now();
+        let Some((java, java_version)) = find_java(self.java_path.as_deref())
+            .await
+            .context("Failed to parse Java version")?
+        else {
+            if let Some(java_path) = &self.java_path {
+                bail!("Failed to run Java at {}", java_path.display());
+            }
+            bail!("Java installation not found! Please download Java for {ARCH} from {JAVA_DOWNLOAD_URL} or use your system's package manager.");
+        };
+
+        /* Some JDK and ViaProxy combinations behave differently on ARM */
+        let java_detection = started_at.elapsed();
+        debug!(
+            "Found Java {java_version} ({ARCH}) at {} in {java_detection:?}",
+            java.display()
+        );
+
+        let client = match self.client {
+            Some(client) => client,
+            None => default_client()?,
+        };
+
+        let mc_version = self.mc_version;
+        let data_dir = match self.data_dir {
+            Some(data_dir) => data_dir,
+            None => minecraft_folder_path::minecraft_dir()
+                .context("Unsupported Platform, set a folder with `with_data_dir`")?,
+        };
+
+        #[rustfmt::skip]
+        let via_proxy_ext = if java_version.major < 17 { "+java8.jar" } else { ".jar" };
+        let via_proxy_version = &self.via_proxy_version;
+        let via_proxy_name = format!("ViaProxy-{via_proxy_version}{via_proxy_ext}");
+        let via_proxy_path = via_proxy_dir(&data_dir)?;
+        let via_proxy_url = format!("https://github.com/ViaVersion/ViaProxy/releases/download/v{via_proxy_version}/{via_proxy_name}");
+        let download = &self.download;
+        let via_proxy_options = DownloadOptions {
+            sha256: self.via_proxy_sha256.clone(),
+            ..download.clone()
+        };
+        let download_started_at = Instant::now();
+        let custom_jar = self.via_proxy_jar.as_deref();
+        let via_proxy_download = async {
+            if let Some(jar) = custom_jar {
+                /* ViaProxy runs in the working dir, so relative paths would resolve from there */
+                return check_jar(jar).await;
+            }
+
+            download_file(
+                &client,
+                &via_proxy_url,
+                &via_proxy_path,
+                &via_proxy_name,
+                &via_proxy_options,
+            )
+            .await
+            .with_context(|| {
+                format!("Failed to download ViaProxy {via_proxy_version} from {via_proxy_url}")
+            })?;
+
+            Ok(via_proxy_path.join(&via_proxy_name))
+        };
+
+        let working_dir = self.working_dir.unwrap_or_else(|| via_proxy_path.clone());
+        tokio::fs::create_dir_all(&working_dir).await?;
+
+        let log_config = if self.structured_logs {
+            let log_config = working_dir.join("azalea-viaversion-log4j2.xml");
+            tokio::fs::write(&log_config, STRUCTURED_LOG_CONFIG)
+                .await
+                .context("Failed to write the log4j2 config")?;
+            /* Java resolves the path from the working dir, which it may be relative to */
+            Some(tokio::fs::canonicalize(&log_config).await?)
+        } else {
+            None
+        };
+
+        /* ViaProxy runs in the working dir, so relative paths would resolve from there */
+        let config_file = match &self.config_file {
+            Some(config_file) => Some(
+                tokio::fs::canonicalize(config_file)
+                    .await
+                    .with_context(|| format!("Failed to find {}", config_file.display()))?,
+            ),
+            None => None,
+        };
+
+        let via_oauth_name = format!("ViaProxyOpenAuthMod-{VIA_OAUTH_VERSION}.jar");
+        let via_oauth_path = working_dir.join("plugins");
+        let via_oauth_url = format!("https://github.com/ViaVersionAddons/ViaProxyOpenAuthMod/releases/download/v{VIA_OAUTH_VERSION}/{via_oauth_name}");
+        let via_oauth_options = DownloadOptions {
+            position: 1, /* Render below ViaProxy's progress bar */
+            sha256: self.via_oauth_sha256.clone(),
+            ..download.clone()
+        };
+        let auth_method = self.auth_method;
+        let custom_via_oauth_jar = self.via_oauth_jar.as_deref();
+        let via_oauth_download = async {
+            if auth_method == AuthMethod::None {
+                return None;
+            }
+
+            if let Some(jar) = custom_via_oauth_jar {
+                let result = async {
+                    let jar = check_jar(jar).await?;
+                    tokio::fs::create_dir_all(&via_oauth_path).await?;
+                    tokio::fs::copy(&jar, via_oauth_path.join(&via_oauth_name))
+                        .await
+                        .with_context(|| format!("Failed to copy {}", jar.display()))?;
+                    anyhow::Ok(())
+                };
+                return Some(result.await);
+            }
+
+            let result = download_file(
+                &client,
+                via_oauth_url,
+                &via_oauth_path,
+                &via_oauth_name,
+                &via_oauth_options,
+            )
+            .await
+            .context("Failed to download ViaProxyOpenAuthMod");
+
+            Some(result)
+        };
+
+        /* Download both jars at once */
+        let (via_proxy_result, via_oauth_result) =
+            future::join(via_proxy_download, via_oauth_download).await;
+        let via_proxy_jar = via_proxy_result?;
+
+        let via_oauth = match (via_oauth_result, auth_method) {
+            (None, _) => false,
+            (Some(Ok(())), _) => true,
+            (Some(Err(error)), AuthMethod::Auto) if custom_via_oauth_jar.is_none() => {
+                warn!("{error:#}, online-mode servers won't work");
+                false
+            }
+            (Some(Err(error)), _) => return Err(error),
+        };
+
+        let plugin_options = DownloadOptions {
+            position: 2, /* Render below both jars' progress bars */
+            ..download.clone()
+        };
+        let mut plugin_files = HashSet::new();
+        for plugin in &self.extra_plugins {
+            let file = plugin.file_name()?;
+            if !plugin_files.insert(file.clone()) {
+                bail!("Two extra plugins would both be saved as {file}");
+            }
+
+            plugin
+                .install(&client, &via_oauth_path, &plugin_options)
+                .await
+                .with_context(|| format!("Failed to install extra plugin {plugin}"))?;
+        }
+
+        let download_time = download_started_at.elapsed();
+        debug!("Downloaded and installed the jars in {download_time:?}");
+
+        #[rustfmt::skip]
+        let via_auth_method = if via_oauth { "OPENAUTHMOD" } else { "NONE" };
+
+        let bind_host = self.bind_addr.map_or_else(
+            || self.bind_host.unwrap_or(self.address_family.loopback()),
+            |bind_addr| bind_addr.ip(),
+        );
+        let config = SpawnConfig {
+            java,
+            jvm_args: self.jvm_args,
+            via_proxy_jar,
+            config_file,
+            log_config,
+            /* Java 8 and older report themselves as 1.x */
+            java_major: if java_version.major == 1 {
+                java_version.minor
+            } else {
+                java_version.major
+            },
+            connect_host: self
+                .connect_host
+                .unwrap_or_else(|| default_connect_host(bind_host)),
+            working_dir,
+            auth_method: via_auth_method,
+            proxy_online_mode: self.proxy_online_mode,
+            persistent: self.persistent,
+            via_debug: self.via_debug,
+            ready_milestone: self.ready_milestone,
+            wildcard_domain_handling: self.wildcard_domain_handling,
+            compression_threshold: self.compression_threshold,
+            legacy_skin_loading: self.legacy_skin_loading,
+            extra_args: self.extra_args,
+            stdout_buffer_size: self.stdout_buffer_size,
+            no_output_timeout: self.no_output_timeout,
+            startup_timeout: self.startup_timeout,
+            output_context_lines: self.output_context_lines,
+            log_file: self.log_file,
+            log_file_max_size: self.log_file_max_size,
+            bind_retries: self.bind_retries,
+            bind_port: self.bind_addr.map(|bind_addr| bind_addr.port()),
+            auto_restart: self.auto_restart,
+            envs: self.envs,
+            clear_env: self.clear_env,
+            runtime: self.runtime.clone(),
+            pre_spawn: self.pre_spawn,
+        };
+
+        let warm_key = self.warm_reuse.then(|| {
+            WarmKey::new(
+                &config,
+                &mc_version,
+                bind_host,
+                self.backend_proxy.as_ref(),
+                &self.extra_plugins,
+            )
+        });
+        let mut warm_process = warm_key.as_ref().and_then(warm::reuse);
+        if let Some(process) = &warm_process {
+            if !process.is_running().await {
+                warm_process = None;
+            }
+        }
+
+        let process = if let Some(process) = warm_process {
+            debug!("Reusing the warm ViaProxy on {}", process.bind_addr());
+            process
+        } else {
+            /* Only reserved now, since a reused warm proxy already listens on a fixed port */
+            let bind_addr = SocketAddr::new(bind_host, config.bind_port.unwrap_or(0));
+            let reserved =
+                reserve_addr(bind_addr).with_context(|| format!("Failed to bind {bind_addr}"))?;
+            let process = Arc::new(ViaProxyProcess::new(
+                config,
+                reserved,
+                mc_version,
+                self.backend_proxy,
+            ));
+            process.spawn().await?;
+            if let Some(warm_key) = warm_key {
+                warm::keep(warm_key, process.clone());
+            }
+
+            process
+        };
+        process.supervise();
+
+        let pending_auth_tasks = Arc::new(PendingAuthTasks::default());
+        process.update_report(|report| {
+            report.timings.java_detection = java_detection;
+            report.timings.download = download_time;
+        });
+
+        let plugin = ViaVersionPlugin {
+            process: process.clone(),
+            client,
+            auth_account: self.auth_account,
+            auth_policy: self.auth_policy,
+            require_auth: self.require_auth,
+            on_auth_complete: self.on_auth_complete,
+            pending_auth_tasks: pending_auth_tasks.clone(),
+            host_literal: self.host_literal,
+            resolve_targets: self.resolve_targets,
+            route: Arc::default(),
+            runtime: self.runtime,
+        };
+
+        Ok((plugin, ViaController::new(process, pending_auth_tasks)))
+    }
+}
+
+/// The folder the jars are downloaded to, inside the data dir.
+///
+/// It's made absolute, since ViaProxy runs in the working dir, which defaults to this folder and
+/// would resolve a relative jar path inside itself.
+///
+/// # Errors
+/// Will return `Err` if the current directory can't be read for a relative `data_dir`.
+fn via_proxy_dir(data_dir: &Path) -> Result<PathBuf> {
+    let data_dir = std::path::absolute(data_dir)
+        .with_context(|| format!("Failed to find {}", data_dir.display()))?;
+    Ok(data_dir.join("azalea-viaversion"))
+}
+
+/// Make sure a jar given instead of a download exists and can be read, and make its path absolute.
+///
+/// # Errors
+/// Will return `Err` if the jar doesn't exist or can't be opened.
+async fn check_jar(jar: &Path) -> Result<PathBuf> {
+    let path = tokio::fs::canonicalize(jar)
+        .await
+        .with_context(|| format!("Jar {} doesn't exist", jar.display()))?;
+    tokio::fs::File::open(&path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    Ok(path)
+}
+
+/// Pick a delay between zero and `max`.
+///
+/// Each [`RandomState`] is seeded differently, which is random enough to spread out startups.
+#[allow(clippy::cast_precision_loss)]
+fn random_delay(max: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Connect over loopback when ViaProxy listens on every interface.
+const fn default_connect_host(bind_host: IpAddr) -> IpAddr {
+    match bind_host {
+        IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        ip => ip,
+    }
+}
+
+/// Build the HTTP client used when the user doesn't provide one.
+pub(crate) fn default_client() -> Result<Client> {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .context("Failed to build HTTP client")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcard_domain_handling_from_str() {
+        for handling in [
+            WildcardDomainHandling::None,
+            WildcardDomainHandling::Public,
+            WildcardDomainHandling::Internal,
+        ] {
+            assert_eq!(
+                handling.as_arg().parse::<WildcardDomainHandling>().unwrap(),
+                handling
+            );
+        }
+        assert_eq!(
+            "internal".parse::<WildcardDomainHandling>().unwrap(),
+            WildcardDomainHandling::Internal
+        );
+        assert!("wildcard".parse::<WildcardDomainHandling>().is_err());
+    }
+
+    #[test]
+    fn test_via_proxy_dir_relative_data_dir() {
+        let dir = via_proxy_dir(Path::new("data")).unwrap();
+        assert!(dir.is_absolute());
+        assert_eq!(
+            dir,
+            std::env::current_dir()
+                .unwrap()
+                .join("data")
+                .join("azalea-viaversion")
+        );
+    }
+
+    #[test]
+    fn test_address_family_loopback() {
+        for (address_family, host_literal) in [
+            (AddressFamily::Auto, "127.0.0.1"),
+            (AddressFamily::V4, "127.0.0.1"),
+            (AddressFamily::V6, "::1"),
+        ] {
+            let bind_host = address_family.loopback();
+            assert_eq!(default_connect_host(bind_host), bind_host);
+            assert_eq!(bind_host.to_string(), host_literal);
+        }
+    }
+
+    #[test]
+    fn test_default_connect_host_unspecified() {
+        let bind_host = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+        assert_eq!(
+            default_connect_host(bind_host),
+            AddressFamily::V6.loopback()
+        );
+    }
+}
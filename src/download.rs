@@ -0,0 +1,595 @@
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use reqwest::{Client, IntoUrl, Response, StatusCode, Url};
+use sha2::{Digest, Sha256};
+use std::{
+    path::{Component, Path},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::{fs::File, io::AsyncWriteExt};
+use tracing::{debug, warn};
+
+use crate::{
+    builder::default_client,
+    metrics,
+    progress::{Progress, ProgressHook},
+    ViaStartError,
+};
+
+/// Try to download and save a file if it doesn't exist.
+///
+/// Existing files that are empty are downloaded again, and the file is only moved into place once
+/// it has been fully written. Connection errors and server errors are retried a few times with a
+/// growing delay. Use [`try_download_file_with_sha256`] to also check its contents.
+///
+/// # Errors
+/// Will return `Err` if `file` isn't a plain file name, or the file fails to download or save.
+pub async fn try_download_file<U, P>(url: U, dir: P, file: &str) -> Result<()>
+where
+    U: IntoUrl + Send + Sync,
+    P: AsRef<Path> + Send + Sync,
+{
+    try_download_file_with_client(&default_client()?, url, dir, file).await
+}
+
+/// [`try_download_file`], but with a client of your own, like one with a proxy or timeouts.
+///
+/// # Errors
+/// Will return `Err` like [`try_download_file`].
+pub async fn try_download_file_with_client<U, P>(
+    client: &Client,
+    url: U,
+    dir: P,
+    file: &str,
+) -> Result<()>
+where
+    U: IntoUrl + Send + Sync,
+    P: AsRef<Path> + Send + Sync,
+{
+    download_file(client, url, dir, file, &DownloadOptions::default()).await
+}
+
+/// [`try_download_file_with_client`], but checking the file against a SHA-256 digest in hex.
+///
+/// A cached file that doesn't match is downloaded again, and a download that doesn't match is
+/// deleted.
+///
+/// # Errors
+/// Will return `Err` with [`ViaStartError::ChecksumMismatch`] if the download doesn't match, or
+/// like [`try_download_file`].
+pub async fn try_download_file_with_sha256<U, P>(
+    client: &Client,
+    url: U,
+    dir: P,
+    file: &str,
+    sha256: &str,
+) -> Result<()>
+where
+    U: IntoUrl + Send + Sync,
+    P: AsRef<Path> + Send + Sync,
+{
+    let options = DownloadOptions {
+        sha256: Some(sha256.to_ascii_lowercase()),
+        ..DownloadOptions::default()
+    };
+    download_file(client, url, dir, file, &options).await
+}
+
+/// How many times a download is retried after a transient failure.
+const DEFAULT_DOWNLOAD_RETRIES: u32 = 4;
+
+/// How long to wait before the first retry, doubled for each one after it.
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Tells apart the temporary files of downloads in this process.
+static PART_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How [`download_file`] fetches a file, set through the builder.
+#[derive(Clone)]
+pub(crate) struct DownloadOptions {
+    pub(crate) progress_hook: Option<ProgressHook>,
+    pub(crate) force: bool,
+    /// Whether to render a progress bar, or `None` to only render one on a terminal.
+    pub(crate) progress_bar: Option<bool>,
+    /// The line the progress bar is rendered on, so parallel downloads don't overwrite each other.
+    pub(crate) position: u16,
+    /// The lowercase hex SHA-256 digest the file must have.
+    pub(crate) sha256: Option<String>,
+    /// How many times a transient failure is retried, downloading the file from scratch.
+    pub(crate) retries: u32,
+    /// Fail instead of downloading files that aren't cached.
+    pub(crate) offline: bool,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DownloadOptions {
+    /// The defaults, usable in `const` builders.
+    pub(crate) const fn new() -> Self {
+        Self {
+            progress_hook: None,
+            force: false,
+            progress_bar: None,
+            position: 0,
+            sha256: None,
+            retries: DEFAULT_DOWNLOAD_RETRIES,
+            offline: false,
+        }
+    }
+}
+
+/// [`try_download_file`], with the builder's options.
+pub(crate) async fn download_file<U, P>(
+    client: &Client,
+    url: U,
+    dir: P,
+    file: &str,
+    options: &DownloadOptions,
+) -> Result<()>
+where
+    U: IntoUrl + Send + Sync,
+    P: AsRef<Path> + Send + Sync,
+{
+    validate_file_name(file)?;
+    tokio::fs::create_dir_all(&dir).await?;
+    let path = dir.as_ref().join(file);
+    match tokio::fs::metadata(&path).await {
+        Ok(_) if options.force && !options.offline => {
+            warn!("Downloading {file} again, ignoring the cache");
+        }
+        Ok(metadata) if metadata.len() > 0 => match &options.sha256 {
+            None => return Ok(()),
+            Some(expected) if file_sha256(&path).await? == *expected => return Ok(()),
+            Some(_) => warn!("{file} doesn't match its checksum, downloading it again"),
+        },
+        Ok(_) => warn!("{file} is empty, downloading it again"),
+        Err(_) => { /* Not downloaded yet */ }
+    }
+
+    if options.offline {
+        return Err(ViaStartError::NotDownloaded { path }.into());
+    }
+
+    let mut url = client.get(url).build()?.url().clone();
+    let mut attempt = 0;
+    loop {
+        let error = match download_once(client, &mut url, dir.as_ref(), file, options).await {
+            Err(error) if attempt < options.retries && is_transient(&error) => error,
+            result => return result,
+        };
+
+        let delay = DOWNLOAD_RETRY_DELAY * 2u32.saturating_pow(attempt);
+        attempt += 1;
+        warn!(
+            "Failed to download {file}, retrying in {delay:?} ({attempt}/{}): {error:#}",
+            options.retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Download a file from scratch, only moving it into place if it's complete.
+///
+/// `url` is replaced with the URL it redirected to, so a retry skips the redirects.
+async fn download_once(
+    client: &Client,
+    url: &mut Url,
+    dir: &Path,
+    file: &str,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let path = dir.join(file);
+
+    /* Redirects are followed, so the length belongs to the final URL, which retries go to */
+    let response = client.get(url.clone()).send().await?;
+    if response.url() != url {
+        debug!("{url} redirected to {}", response.url());
+        url.clone_from(response.url());
+    }
+    let response = response.error_for_status()?;
+    let expected = response.content_length().map(usize::try_from).transpose()?;
+
+    let progress = Progress::new(
+        file,
+        expected,
+        options.position,
+        options.progress_hook.clone(),
+        options.progress_bar,
+    )?;
+
+    /* Write to a temporary file so an interrupted download never looks complete. It's unique to
+     * this download, so processes sharing the folder never move each other's halves into place */
+    let part_id = PART_FILE_ID.fetch_add(1, Ordering::Relaxed);
+    let part_path = dir.join(format!("{file}.{}-{part_id}.part", std::process::id()));
+    let result = save_download(
+        response, file, &part_path, &path, expected, progress, options,
+    )
+    .await;
+    if result.is_err() {
+        /* Every download has its own temporary file, so they'd pile up otherwise */
+        let _ = tokio::fs::remove_file(&part_path).await;
+    }
+
+    result
+}
+
+/// Write a download to `part_path`, and move it to `path` if it's complete.
+async fn save_download(
+    response: Response,
+    file: &str,
+    part_path: &Path,
+    path: &Path,
+    expected: Option<usize>,
+    mut progress: Progress,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let mut part_file = File::create(part_path).await?;
+    let mut stream = response.bytes_stream();
+    let mut received = 0;
+    let mut hasher = Sha256::new();
+
+    while let Some(item) = stream.next().await {
+        let chunk = item?;
+        part_file
+            .write_all(&chunk)
+            .await
+            .map_err(|error| storage_error(error, path))?;
+        received += chunk.len();
+        hasher.update(&chunk);
+        progress.update(chunk.len())?;
+    }
+
+    part_file
+        .flush()
+        .await
+        .map_err(|error| storage_error(error, path))?;
+    drop(part_file);
+
+    progress.finish()?;
+
+    let actual = format!("{:x}", hasher.finalize());
+    let error = match (expected, &options.sha256) {
+        _ if received == 0 => Some(ViaStartError::EmptyDownload {
+            file: file.to_owned(),
+        }),
+        (Some(expected), _) if received != expected => Some(ViaStartError::IncompleteDownload {
+            file: file.to_owned(),
+            expected,
+            received,
+        }),
+        (_, Some(expected)) if actual != *expected => Some(ViaStartError::ChecksumMismatch {
+            file: file.to_owned(),
+            expected: expected.clone(),
+            actual,
+        }),
+        _ => None,
+    };
+
+    if let Some(error) = error {
+        return Err(error.into());
+    }
+
+    tokio::fs::rename(part_path, path).await?;
+    metrics::record_download(file);
+
+    Ok(())
+}
+
+/// Whether a failed download may work if it's tried again.
+///
+/// Connection errors, timeouts, server errors, rate limits and truncated downloads are transient,
+/// while other client errors like a missing file, and errors saving the file, aren't.
+fn is_transient(error: &anyhow::Error) -> bool {
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return match error.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => error.is_connect() || error.is_timeout() || error.is_body(),
+        };
+    }
+
+    matches!(
+        error.downcast_ref::<ViaStartError>(),
+        Some(ViaStartError::EmptyDownload { .. } | ViaStartError::IncompleteDownload { .. })
+    )
+}
+
+/// The lowercase hex SHA-256 digest of a file on disk.
+async fn file_sha256(path: &Path) -> Result<String> {
+    let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(format!("{:x}", Sha256::digest(&contents)))
+}
+
+/// Make sure a file name can't escape the directory it's joined onto.
+pub(crate) fn validate_file_name(file: &str) -> Result<()> {
+    let mut components = Path::new(file).components();
+    let is_plain = match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) => name == file,
+        _ => false,
+    };
+
+    if !is_plain || file.contains(['/', '\\']) {
+        return Err(ViaStartError::InvalidFileName {
+            file: file.to_owned(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Turn running out of disk space into a clear error.
+fn storage_error(error: std::io::Error, path: &Path) -> anyhow::Error {
+    if error.kind() == std::io::ErrorKind::StorageFull {
+        ViaStartError::StorageFull {
+            path: path.to_path_buf(),
+        }
+        .into()
+    } else {
+        error.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+    use tokio::net::TcpListener;
+
+    /// Serve every request on a local port with the response `respond` picks for it.
+    async fn serve(respond: fn(&str) -> &'static str) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            use tokio::io::AsyncReadExt;
+
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 1024];
+                let len = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..len]);
+                stream
+                    .write_all(respond(&request).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn test_download_file_redirect() {
+        let addr = serve(|request| {
+            /* GitHub redirects release assets to its CDN */
+            if request.starts_with("GET /release ") {
+                "HTTP/1.1 302 Found\r\nLocation: /cdn\r\nContent-Length: 8\r\nConnection: close\r\n\r\nredirect"
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\njar"
+            }
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("azalea-viaversion-{}", addr.port()));
+        let url = format!("http://{addr}/release");
+        download_file(
+            &Client::new(),
+            url,
+            &dir,
+            "ViaProxy.jar",
+            &DownloadOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let jar = tokio::fs::read(dir.join("ViaProxy.jar")).await.unwrap();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        assert_eq!(jar, b"jar");
+    }
+
+    #[tokio::test]
+    async fn test_download_file_retries_final_url() {
+        static RELEASE_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        static CDN_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        let addr = serve(|request| {
+            if request.starts_with("GET /release ") {
+                RELEASE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+                return "HTTP/1.1 302 Found\r\nLocation: /cdn\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            }
+
+            match CDN_REQUESTS.fetch_add(1, Ordering::Relaxed) {
+                0 => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                _ => "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\njar",
+            }
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("azalea-viaversion-{}", addr.port()));
+        let url = format!("http://{addr}/release");
+        let result = download_file(
+            &Client::new(),
+            url,
+            &dir,
+            "ViaProxy.jar",
+            &DownloadOptions::default(),
+        )
+        .await;
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        result.unwrap();
+        assert_eq!(RELEASE_REQUESTS.load(Ordering::Relaxed), 1);
+        assert_eq!(CDN_REQUESTS.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_download_file_cached() {
+        let addr =
+            serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\nnew").await;
+
+        let dir = std::env::temp_dir().join(format!("azalea-viaversion-{}", addr.port()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("ViaProxy.jar"), b"cached")
+            .await
+            .unwrap();
+        try_download_file(format!("http://{addr}/release"), &dir, "ViaProxy.jar")
+            .await
+            .unwrap();
+
+        let jar = tokio::fs::read(dir.join("ViaProxy.jar")).await.unwrap();
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        assert_eq!(jar, b"cached");
+    }
+
+    #[tokio::test]
+    async fn test_download_file_checksum() {
+        let addr =
+            serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\njar").await;
+
+        let dir = std::env::temp_dir().join(format!("azalea-viaversion-{}", addr.port()));
+        let url = format!("http://{addr}/release");
+        let client = Client::new();
+
+        /* A corrupt cached jar is replaced */
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        tokio::fs::write(dir.join("ViaProxy.jar"), b"corrupt")
+            .await
+            .unwrap();
+        let sha256 = "0163F1EEA7894350060624D315234D40C508AB251BA121714E234503045FAADD";
+        try_download_file_with_sha256(&client, &url, &dir, "ViaProxy.jar", sha256)
+            .await
+            .unwrap();
+        let jar = tokio::fs::read(dir.join("ViaProxy.jar")).await.unwrap();
+        assert_eq!(jar, b"jar");
+
+        /* A download that doesn't match is deleted */
+        let error = try_download_file_with_sha256(&client, &url, &dir, "Other.jar", "00")
+            .await
+            .unwrap_err();
+        let exists = std::fs::read_dir(&dir).unwrap().any(|entry| {
+            entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("Other.jar")
+        });
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        assert!(matches!(
+            error.downcast_ref::<ViaStartError>(),
+            Some(ViaStartError::ChecksumMismatch { .. })
+        ));
+        assert!(!exists);
+    }
+
+    #[tokio::test]
+    async fn test_download_file_retries() {
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        let addr = serve(|request| {
+            if request.starts_with("GET /missing ") {
+                return "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            }
+            if request.starts_with("GET /forbidden ") {
+                return "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+            }
+
+            /* Fail the first request, like a flaky CDN */
+            match REQUESTS.fetch_add(1, Ordering::Relaxed) {
+                0 => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                _ => "HTTP/1.1 200 OK\r\nContent-Length: 3\r\nConnection: close\r\n\r\njar",
+            }
+        })
+        .await;
+
+        let dir = std::env::temp_dir().join(format!("azalea-viaversion-{}", addr.port()));
+        let client = Client::new();
+        let options = DownloadOptions::default();
+        let result = download_file(
+            &client,
+            format!("http://{addr}/release"),
+            &dir,
+            "ViaProxy.jar",
+            &options,
+        )
+        .await;
+        let jar = tokio::fs::read(dir.join("ViaProxy.jar")).await;
+
+        let started_at = std::time::Instant::now();
+        let missing = download_file(
+            &client,
+            format!("http://{addr}/missing"),
+            &dir,
+            "Missing.jar",
+            &options,
+        )
+        .await;
+        let forbidden = download_file(
+            &client,
+            format!("http://{addr}/forbidden"),
+            &dir,
+            "Forbidden.jar",
+            &options,
+        )
+        .await;
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        result.unwrap();
+        assert_eq!(jar.unwrap(), b"jar");
+        assert_eq!(REQUESTS.load(Ordering::Relaxed), 2);
+        assert!(missing.is_err());
+        assert!(forbidden.is_err());
+        assert!(started_at.elapsed() < DOWNLOAD_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_validate_file_name() {
+        assert!(validate_file_name("ViaProxy-3.3.7.jar").is_ok());
+        for file in [
+            "../evil",
+            "/etc/passwd",
+            "plugins/evil.jar",
+            "..\\evil",
+            "..",
+            "",
+        ] {
+            let error = validate_file_name(file).unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ViaStartError>(),
+                Some(ViaStartError::InvalidFileName { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_file_offline() {
+        let dir =
+            std::env::temp_dir().join(format!("azalea-viaversion-offline-{}", std::process::id()));
+        let options = DownloadOptions {
+            offline: true,
+            ..DownloadOptions::default()
+        };
+        /* Nothing listens on the discard port, so a request would fail with another error */
+        let url = "http://127.0.0.1:9/release";
+        let client = Client::new();
+
+        let missing = download_file(&client, url, &dir, "ViaProxy.jar", &options).await;
+        tokio::fs::write(dir.join("ViaProxy.jar"), b"jar")
+            .await
+            .unwrap();
+        let cached = download_file(&client, url, &dir, "ViaProxy.jar", &options).await;
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        assert!(matches!(
+            missing.unwrap_err().downcast_ref::<ViaStartError>(),
+            Some(ViaStartError::NotDownloaded { path }) if path.ends_with("ViaProxy.jar")
+        ));
+        cached.unwrap();
+    }
+}
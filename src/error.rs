@@ -0,0 +1,112 @@
+use std::{fmt, net::SocketAddr, path::PathBuf, time::Duration};
+
+/// Errors that can happen while downloading or starting ViaProxy.
+///
+/// These are wrapped in [`anyhow::Error`], use [`anyhow::Error::downcast_ref`] to inspect them.
+/// Downloads that fail are never kept, so starting again downloads the file again.
+#[derive(Debug)]
+pub enum ViaStartError {
+    /// A download finished without writing any bytes.
+    EmptyDownload { file: String },
+    /// A download finished before the expected amount of bytes were written.
+    IncompleteDownload {
+        file: String,
+        expected: usize,
+        received: usize,
+    },
+    /// A download's SHA-256 digest didn't match the expected one.
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+    /// The disk ran out of space while saving a download.
+    StorageFull { path: PathBuf },
+    /// A download's file name would be saved outside of its directory.
+    InvalidFileName { file: String },
+    /// ViaProxy was spawned, but didn't print anything before the timeout.
+    NoOutput { timeout: Duration },
+    /// ViaProxy was spawned and printed output, but wasn't ready before the timeout.
+    StartupTimeout { timeout: Duration },
+    /// ViaProxy logged a fatal error before it was ready.
+    Fatal { message: String },
+    /// `java` ran, but its output wasn't a Java version.
+    UnrecognizedJava { output: String },
+    /// ViaProxy couldn't listen on its address, usually because something else took the port.
+    PortInUse { addr: SocketAddr },
+    /// A file isn't downloaded yet, but downloads are turned off with
+    /// [`ViaVersionBuilder::with_offline`](crate::ViaVersionBuilder::with_offline).
+    NotDownloaded { path: PathBuf },
+}
+
+impl fmt::Display for ViaStartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyDownload { file } => {
+                write!(f, "Downloaded {file} is empty")
+            }
+            Self::IncompleteDownload {
+                file,
+                expected,
+                received,
+            } => {
+                write!(
+                    f,
+                    "Downloaded {file} is incomplete ({received} of {expected} bytes)"
+                )
+            }
+            Self::ChecksumMismatch {
+                file,
+                expected,
+                actual,
+            } => {
+                write!(
+                    f,
+                    "Downloaded {file} has SHA-256 {actual} instead of {expected}"
+                )
+            }
+            Self::StorageFull { path } => {
+                write!(f, "Not enough disk space to save {}", path.display())
+            }
+            Self::InvalidFileName { file } => {
+                write!(
+                    f,
+                    "Refusing to save download with unsafe file name {file:?}"
+                )
+            }
+            Self::NoOutput { timeout } => {
+                write!(
+                    f,
+                    "ViaProxy printed nothing within {timeout:?}, is the JVM stuck or missing native libraries?"
+                )
+            }
+            Self::StartupTimeout { timeout } => {
+                write!(f, "ViaProxy wasn't ready within {timeout:?}")
+            }
+            Self::Fatal { message } => {
+                write!(f, "ViaProxy failed to start: {message}")
+            }
+            Self::UnrecognizedJava { output } => {
+                write!(
+                    f,
+                    "`java` doesn't look like a JRE or JDK, it printed: {output}"
+                )
+            }
+            Self::PortInUse { addr } => {
+                write!(
+                    f,
+                    "ViaProxy failed to bind {addr}, is the port already in use?"
+                )
+            }
+            Self::NotDownloaded { path } => {
+                write!(
+                    f,
+                    "{} not found and offline mode is on, run once online or pass the jar's path to the builder",
+                    path.display()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ViaStartError {}
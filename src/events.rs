@@ -0,0 +1,38 @@
+use azalea::ecs::prelude::*;
+use std::process::ExitStatus;
+
+use crate::AuthError;
+
+/// Sent when one of ViaProxy's translation layers logs a warning.
+///
+/// These often come right before a bot is kicked for a packet that didn't translate cleanly.
+/// ViaProxy doesn't say which connection a warning is about, so it isn't tied to a bot.
+#[derive(Clone, Debug, Event)]
+pub struct ViaTranslationWarning {
+    /// The warning's message, without the timestamp and log level.
+    pub detail: String,
+}
+
+/// Sent when a bot's OpenAuthMod join fails, so the bot can be retried, given another account or
+/// disconnected.
+///
+/// ViaProxy is told the join failed, so the bot is usually disconnected right after. The same
+/// result is also inserted as an [`OpenAuthModAuthResult`](crate::OpenAuthModAuthResult).
+#[derive(Clone, Debug, Event)]
+pub struct ViaOAuthFailed {
+    /// The bot that failed to join.
+    pub entity: Entity,
+    /// Why the join failed.
+    pub error: AuthError,
+}
+
+/// Sent when ViaProxy exited on its own and was started again by
+/// [`ViaVersionBuilder::with_auto_restart`](crate::ViaVersionBuilder::with_auto_restart).
+///
+/// It's sent once the new ViaProxy is ready. Bots that were connected through the old one were
+/// disconnected when it exited.
+#[derive(Clone, Debug, Event)]
+pub struct ViaProxyRestarted {
+    /// How the old ViaProxy exited.
+    pub exit_status: ExitStatus,
+}
@@ -1,11 +1,6 @@
 use anyhow::{Context, Result};
 use azalea::{
-    app::{App, Plugin, PreUpdate, Startup},
-    auth::sessionserver::{
-        join_with_server_id_hash,
-        ClientSessionServerError::{ForbiddenOperation, InvalidSession},
-    },
-    buf::AzaleaRead,
+    app::{App, AppExit, Last, Plugin, PreUpdate, Startup},
     ecs::prelude::*,
     packet_handling::login::{
         process_packet_events, IgnoreQueryIds, LoginPacketEvent, LoginSendPacketQueue,
@@ -15,191 +10,451 @@ use azalea::{
         packets::login::{
             ClientboundLoginPacket, ServerboundCustomQueryAnswer, ServerboundLoginPacket,
         },
+        resolver::resolve_address,
         ServerAddress,
     },
     swarm::Swarm,
+    JoinOpts,
 };
-use futures_util::StreamExt;
-use kdam::{tqdm, BarExt};
 use lazy_regex::regex_captures;
 use reqwest::Client;
-use reqwest::IntoUrl;
 use semver::Version;
-use std::{io::Cursor, net::SocketAddr, path::Path, process::Stdio};
+use std::{
+    env::consts::EXE_SUFFIX,
+    ffi::OsStr,
+    fmt,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::{
-    fs::File,
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
-    net::TcpListener,
+    net::{TcpListener, TcpSocket},
     process::Command,
+    runtime::Handle,
+    task::JoinHandle,
 };
-use tracing::{error, trace};
+use tracing::{debug, error, warn};
+
+use crate::{
+    auth::{authenticate, AuthCompleteHook, AuthPolicy, PendingAuthTasks},
+    oam::OpenAuthModRequest,
+    process::ViaProxyProcess,
+    versions::version_for_protocol,
+};
+
+mod auth;
+mod builder;
+mod download;
+mod error;
+mod events;
+mod metrics;
+mod oam;
+mod output;
+mod plugins;
+mod process;
+mod progress;
+mod proxy;
+mod report;
+mod versions;
+mod warm;
+
+pub use auth::{poll_all_oam_join_tasks, AuthError, OpenAuthModAuthResult, OpenAuthModJoinTask};
+pub use builder::{
+    AddressFamily, AuthMethod, ReadyMilestone, ViaVersionBuilder, WildcardDomainHandling,
+};
+pub use download::{
+    try_download_file, try_download_file_with_client, try_download_file_with_sha256,
+};
+pub use error::ViaStartError;
+pub use events::{ViaOAuthFailed, ViaProxyRestarted, ViaTranslationWarning};
+pub use process::ViaController;
+pub use progress::DownloadProgress;
+pub use proxy::{redacted_proxy_url, BackendProxy};
+pub use report::{StartupTimings, ViaStartupReport};
+pub use versions::{check_version, detect_version, is_bedrock, supported_versions};
 
 const JAVA_DOWNLOAD_URL: &str = "https://adoptium.net/installation";
+/// The newest Java release the bundled ViaProxy is known to work on.
+const JAVA_TESTED_VERSION: u64 = 23;
+const JAVA_LTS_VERSION: u64 = 21;
 const VIA_OAUTH_VERSION: Version = Version::new(1, 0, 0);
 const VIA_PROXY_VERSION: Version = Version::new(3, 3, 7);
 
 #[derive(Clone, Resource)]
 pub struct ViaVersionPlugin {
-    bind_addr: SocketAddr,
-    mc_version: String,
+    process: Arc<ViaProxyProcess>,
+    client: Client,
+    auth_account: Option<Account>,
+    auth_policy: AuthPolicy,
+    require_auth: bool,
+    on_auth_complete: Option<AuthCompleteHook>,
+    pending_auth_tasks: Arc<PendingAuthTasks>,
+    host_literal: Option<String>,
+    resolve_targets: bool,
+    /// How the swarm's address was last routed, to re-encode it and tell when azalea reset it.
+    route: Arc<parking_lot::Mutex<Option<Route>>>,
+    runtime: Option<Handle>,
+}
+
+impl fmt::Debug for ViaVersionPlugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ViaVersionPlugin")
+            .field("bind_addr", &self.process.bind_addr())
+            .field("connect_addr", &self.process.connect_addr())
+            .field("mc_version", &self.process.mc_version())
+            .field("backend_proxy", &self.process.backend_proxy())
+            .field("client", &self.client)
+            .field(
+                "auth_account",
+                &self.auth_account.as_ref().map(|account| &account.username),
+            )
+            .field("auth_policy", &self.auth_policy)
+            .field("require_auth", &self.require_auth)
+            .field("on_auth_complete", &self.on_auth_complete.is_some())
+            .field("pending_auth_tasks", &self.pending_auth_tasks.count())
+            .field("host_literal", &self.host_literal)
+            .field("resolve_targets", &self.resolve_targets)
+            .field("runtime", &self.runtime)
+            .finish()
+    }
 }
 
 impl Plugin for ViaVersionPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(self.clone())
+            .insert_resource(self.process.report())
+            .add_event::<ViaTranslationWarning>()
+            .add_event::<ViaOAuthFailed>()
+            .add_event::<ViaProxyRestarted>()
             .add_systems(Startup, Self::handle_change_address)
-            .add_systems(PreUpdate, Self::handle_oauth.before(process_packet_events));
+            .add_systems(
+                PreUpdate,
+                (
+                    Self::handle_change_address,
+                    Self::handle_oauth.before(process_packet_events),
+                    poll_all_oam_join_tasks,
+                    Self::handle_translation_warnings,
+                    Self::handle_restarts,
+                    Self::handle_report,
+                ),
+            )
+            .add_systems(Last, Self::handle_app_exit);
+
+        #[cfg(feature = "metrics")]
+        app.add_systems(PreUpdate, metrics::record_connections);
     }
 }
 
 impl ViaVersionPlugin {
+    /// Configure a ViaProxy instance before starting it.
+    #[must_use]
+    pub fn builder(mc_version: impl ToString) -> ViaVersionBuilder {
+        ViaVersionBuilder::new(mc_version.to_string())
+    }
+
+    /// [`Self::builder`], but targeting the release with this protocol version number, like `767`
+    /// for 1.21.
+    ///
+    /// # Errors
+    /// Will return `Err` if the bundled ViaProxy doesn't support that protocol version.
+    pub fn builder_for_protocol(protocol: i32) -> Result<ViaVersionBuilder> {
+        let mc_version = version_for_protocol(protocol)
+            .with_context(|| format!("ViaProxy doesn't support protocol version {protocol}"))?;
+        Ok(Self::builder(mc_version))
+    }
+
     /// Download and start a ViaProxy instance.
     ///
+    /// Use [`ViaVersionPlugin::builder`] to also get a [`ViaController`].
+    ///
     /// # Panics
     /// Will panic if java fails to parse, files fail to download, or ViaProxy fails to start.
     pub async fn start(mc_version: impl ToString) -> Self {
-        let Some(java_version) = try_find_java_version().await.expect("Failed to parse") else {
-            panic!("Java installation not found! Please download Java from {JAVA_DOWNLOAD_URL} or use your system's package manager.");
-        };
+        let (plugin, _controller) = Self::builder(mc_version)
+            .start()
+            .await
+            .expect("Failed to start ViaProxy");
 
-        let mc_version = mc_version.to_string();
-        let mc_path = minecraft_folder_path::minecraft_dir().expect("Unsupported Platform");
+        plugin
+    }
 
-        #[rustfmt::skip]
-        let via_proxy_ext = if java_version.major < 17 { "+java8.jar" } else { ".jar" };
-        let via_proxy_name = format!("ViaProxy-{VIA_PROXY_VERSION}{via_proxy_ext}");
-        let via_proxy_path = mc_path.join("azalea-viaversion");
-        let via_proxy_url = format!("https://github.com/ViaVersion/ViaProxy/releases/download/v{VIA_PROXY_VERSION}/{via_proxy_name}");
-        try_download_file(via_proxy_url, &via_proxy_path, &via_proxy_name)
-            .await
-            .expect("Failed to download ViaProxy");
+    /// Route bots through a ViaProxy that's already running on `bind_addr` and translating to
+    /// `mc_version`.
+    ///
+    /// Use [`ViaVersionBuilder::connect_existing`] to also configure auth.
+    ///
+    /// # Errors
+    /// Will return `Err` if the HTTP client for Mojang's session server fails to build.
+    pub fn connect_existing(bind_addr: SocketAddr, mc_version: impl ToString) -> Result<Self> {
+        let (plugin, _controller) = Self::builder(mc_version).connect_existing(bind_addr)?;
+        Ok(plugin)
+    }
+
+    /// Ping `address` and start a ViaProxy instance targeting the version it runs.
+    ///
+    /// Use [`detect_version`] with [`ViaVersionPlugin::builder`] to also configure ViaProxy.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server fails to be pinged, runs a version ViaProxy doesn't support,
+    /// or ViaProxy fails to start.
+    pub async fn start_auto_version(address: &ServerAddress) -> Result<(Self, ViaController)> {
+        let mc_version = detect_version(address).await?;
+        debug!("Detected that {} runs {mc_version}", address.host);
+
+        Self::builder(mc_version).start().await
+    }
+
+    /// Details ViaProxy printed while it was starting.
+    #[must_use]
+    pub fn startup_report(&self) -> ViaStartupReport {
+        self.process.report()
+    }
+
+    /// The running ViaProxy version and build, for bug reports.
+    #[must_use]
+    pub fn running_via_proxy_version(&self) -> Option<String> {
+        self.process.report().via_proxy_version
+    }
+
+    /// The address ViaProxy is listening on, which is picked when it starts.
+    ///
+    /// This can change if a [`ViaController`] restarts ViaProxy and its port was taken.
+    #[must_use]
+    pub fn bind_addr(&self) -> SocketAddr {
+        self.process.bind_addr()
+    }
+
+    /// The Minecraft version ViaProxy translates to.
+    ///
+    /// This is owned since [`ViaController::restart_with_version`] can change it.
+    #[must_use]
+    pub fn mc_version(&self) -> String {
+        self.process.mc_version()
+    }
+
+    /// How many OpenAuthMod joins are still waiting on Mojang, across every bot.
+    ///
+    /// Use [`ViaController::wait_for_auth_tasks`] to wait for them from outside the ECS.
+    #[must_use]
+    pub fn pending_auth_tasks(&self) -> usize {
+        self.pending_auth_tasks.count()
+    }
+
+    /// The proxy ViaProxy connects to servers through, if there is one.
+    ///
+    /// Use [`BackendProxy::redacted_url`] to log it without its password.
+    #[must_use]
+    pub fn proxy(&self) -> Option<BackendProxy> {
+        self.process.backend_proxy()
+    }
+
+    /// Build [`JoinOpts`] that route a bot to `target` through ViaProxy.
+    ///
+    /// The plugin only rewrites the swarm's address, since the Azalea version it's built against
+    /// connects bots without a join event it could hook. Pass these to [`Swarm::add_with_opts`]
+    /// when bots in the same swarm join different servers, a `custom_address` set any other way
+    /// isn't routed through ViaProxy.
+    #[must_use]
+    pub fn join_opts(&self, target: &ServerAddress) -> JoinOpts {
+        JoinOpts::new()
+            .custom_address(self.via_address(target))
+            .custom_resolved_address(self.process.connect_addr())
+    }
 
-        let via_oauth_name = format!("ViaProxyOpenAuthMod-{VIA_OAUTH_VERSION}.jar");
-        let via_oauth_path = via_proxy_path.join("plugins");
-        let via_oauth_url = format!("https://github.com/ViaVersionAddons/ViaProxyOpenAuthMod/releases/download/v{VIA_OAUTH_VERSION}/{via_oauth_name}");
-        try_download_file(via_oauth_url, &via_oauth_path, &via_oauth_name)
+    /// [`Self::join_opts`], but resolving `target` here and giving ViaProxy its IP, like
+    /// [`ViaVersionBuilder::with_resolve_targets`] does for the swarm's address.
+    ///
+    /// # Errors
+    /// Will return `Err` if `target` fails to resolve.
+    pub async fn resolved_join_opts(&self, target: &ServerAddress) -> Result<JoinOpts> {
+        let resolved_address = resolve_address(target)
             .await
-            .expect("Failed to download ViaProxyOpenAuthMod");
-
-        let bind_addr = try_find_free_addr().await.expect("Failed to bind");
-        let mut child = Command::new("java")
-            /* Java Args */
-            .args(["-jar", &via_proxy_name])
-            /* ViaProxy Args */
-            .arg("cli")
-            .args(["--auth-method", "OPENAUTHMOD"])
-            .args(["--bind-address", &bind_addr.to_string()])
-            .args(["--target-address", "127.0.0.1:0"])
-            .args(["--target-version", &mc_version])
-            .args(["--wildcard-domain-handling", "INTERNAL"])
-            .current_dir(via_proxy_path)
-            .stdout(Stdio::piped())
-            .spawn()
-            .expect("Failed to spawn");
-
-        let (tx, mut rx) = tokio::sync::watch::channel(());
-        tokio::spawn(async move {
-            let mut stdout = child.stdout.as_mut().expect("Failed to get stdout");
-            let mut reader = BufReader::new(&mut stdout);
-            let mut line = String::new();
-
-            loop {
-                line.clear();
-                reader.read_line(&mut line).await.expect("Failed to read");
-
-                trace!("{}", line.trim());
-                if line.contains("Finished mapping loading") {
-                    let _ = tx.send(());
-                }
-            }
-        });
+            .with_context(|| format!("Failed to resolve {}", target.host))?;
+        let target = ServerAddress {
+            host: resolved_target(&target.host, resolved_address.ip()),
+            port: resolved_address.port(),
+        };
 
-        /* Wait until ViaProxy is ready */
-        let _ = rx.changed().await;
+        Ok(self.join_opts(&target))
+    }
 
-        Self {
-            bind_addr,
-            mc_version,
+    /// Encode the target server into the address ViaProxy expects.
+    fn via_address(&self, target: &ServerAddress) -> ServerAddress {
+        ServerAddress {
+            host: encode_host(
+                &target.host,
+                target.port,
+                &self.host_literal(),
+                &self.process.mc_version(),
+            ),
+            port: target.port,
         }
     }
 
+    pub(crate) fn on_auth_complete(&self) -> Option<&AuthCompleteHook> {
+        self.on_auth_complete.as_ref()
+    }
+
+    /// The first segment of encoded hosts, the address bots use to reach ViaProxy.
+    fn host_literal(&self) -> String {
+        self.host_literal
+            .clone()
+            .unwrap_or_else(|| default_host_literal(self.process.connect_addr()))
+    }
+
+    /// Route the swarm through ViaProxy.
+    ///
+    /// This runs every update as well as at startup, so the route is restored if azalea resets the
+    /// swarm's address, like when reconnecting, and an address that's only set after startup is
+    /// still routed before bots join with it. Rejoins happen in [`Update`](azalea::app::Update),
+    /// so the route is always checked right before them. It's logged at `debug` when the route had
+    /// to be restored.
     #[allow(clippy::needless_pass_by_value)]
     pub fn handle_change_address(plugin: Res<Self>, swarm: Res<Swarm>) {
-        let ServerAddress { host, port } = swarm.address.read().clone();
+        let mut address = swarm.address.write();
+        let mut resolved_address = swarm.resolved_address.write();
+        let mut route = plugin.route.lock();
+        let routed_before = route.is_some();
+        let changed = route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            plugin.process.connect_addr(),
+            &plugin.host_literal(),
+            &plugin.process.mc_version(),
+            plugin.resolve_targets,
+        );
 
-        // sadly, the first part of the resolved address is unused as viaproxy will resolve it on its own
-        // more info: https://github.com/ViaVersion/ViaProxy/issues/338
-        let data_after_null_byte = host.split_once('\x07').map(|(_, data)| data);
+        if changed && routed_before {
+            debug!("The swarm's address was reset, routing it through ViaProxy again");
+        }
+    }
 
-        let mut connection_host = format!(
-            "localhost\x07{host}\x07{version}",
-            version = plugin.mc_version
-        );
-        if let Some(data) = data_after_null_byte {
-            connection_host.push('\0');
-            connection_host.push_str(data);
+    /// Send a [`ViaTranslationWarning`] for every translation warning ViaProxy logged.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn handle_translation_warnings(
+        plugin: Res<Self>,
+        mut events: EventWriter<ViaTranslationWarning>,
+    ) {
+        for detail in plugin.process.drain_translation_warnings() {
+            events.send(ViaTranslationWarning { detail });
         }
+    }
 
-        *swarm.address.write() = ServerAddress {
-            port,
-            host: connection_host,
-        };
+    /// Send a [`ViaProxyRestarted`] for every time ViaProxy was restarted after exiting on its own.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn handle_restarts(plugin: Res<Self>, mut events: EventWriter<ViaProxyRestarted>) {
+        for exit_status in plugin.process.drain_restarts() {
+            events.send(ViaProxyRestarted { exit_status });
+        }
+    }
 
-        /* Must wait to be written until after reading above */
-        *swarm.resolved_address.write() = plugin.bind_addr;
+    /// Update the [`ViaStartupReport`] resource after ViaProxy restarted, so it describes the
+    /// running process.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn handle_report(plugin: Res<Self>, mut report: ResMut<ViaStartupReport>) {
+        report.set_if_neq(plugin.process.report());
     }
 
+    /// Kill ViaProxy when the app exits, so it doesn't keep its port after the app is gone.
+    ///
+    /// ViaProxy is also spawned with `kill_on_drop`, which kills it once the plugin and its
+    /// controllers are dropped, but apps that exit the process directly never drop them.
+    /// A proxy kept warm with [`ViaVersionBuilder::with_warm_reuse`] is left running for the next
+    /// app in this process.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn handle_app_exit(plugin: Res<Self>, mut events: EventReader<AppExit>) {
+        if events.read().next().is_some() {
+            plugin.process.start_kill();
+        }
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
     pub fn handle_oauth(
+        mut commands: Commands,
+        plugin: Res<Self>,
         mut events: EventReader<LoginPacketEvent>,
         mut query: Query<(&mut IgnoreQueryIds, &Account, &LoginSendPacketQueue)>,
+        mut failures: EventWriter<ViaOAuthFailed>,
     ) {
         for event in events.read().cloned() {
             let ClientboundLoginPacket::CustomQuery(packet) = &*event.packet else {
                 continue;
             };
 
-            if packet.identifier.to_string().as_str() != "oam:join" {
-                continue;
-            }
-
-            let mut buf = Cursor::new(&*packet.data);
-            let Ok(hash) = String::azalea_read(&mut buf) else {
-                error!("Failed to read server id hash from oam:join packet");
-                continue;
+            let identifier = packet.identifier.to_string();
+            let hash = match OpenAuthModRequest::parse(&identifier, &packet.data) {
+                Ok(Some(OpenAuthModRequest::Join { server_id_hash })) => server_id_hash,
+                Ok(Some(request)) => {
+                    warn!("OpenAuthMod request {request:?} isn't supported, leaving it to azalea");
+                    continue;
+                }
+                Ok(None) => {
+                    debug!(
+                        "Leaving custom query {identifier} to azalea, it isn't from OpenAuthMod"
+                    );
+                    continue;
+                }
+                Err(error) => {
+                    error!("{error:#}");
+                    continue;
+                }
             };
 
             let Ok((mut ignored_ids, account, queue)) = query.get_mut(event.entity) else {
                 continue;
             };
 
+            if is_bedrock(&plugin.process.mc_version()) {
+                /* Leave it to azalea, which answers that it doesn't understand the query */
+                warn!("Bedrock servers authenticate with Xbox Live, which OpenAuthMod can't do, so the join is left unanswered");
+                continue;
+            }
+
             ignored_ids.insert(packet.transaction_id);
 
-            let Some(access_token) = &account.access_token else {
-                error!("Server is online-mode, but our account is offline-mode");
+            /* Authenticate with the service account instead, if there is one */
+            let account = plugin.auth_account.as_ref().unwrap_or(account);
+            if account.access_token.is_none() {
+                if !plugin.require_auth {
+                    error!("Server is online-mode, but our account is offline-mode");
+                    continue;
+                }
+
+                /* Fail the join right away instead of leaving the server to time it out */
+                let error = AuthError::OfflineAccount(account.username.clone());
+                error!("{error}");
+                metrics::record_auth(false);
+                let _ = queue.tx.send(ServerboundLoginPacket::CustomQueryAnswer(
+                    ServerboundCustomQueryAnswer {
+                        transaction_id: packet.transaction_id,
+                        data: Some(vec![0].into()),
+                    },
+                ));
+                if let Some(hook) = &plugin.on_auth_complete {
+                    hook(event.entity, Err(error.clone()));
+                }
+                failures.send(ViaOAuthFailed {
+                    entity: event.entity,
+                    error: error.clone(),
+                });
+                commands
+                    .entity(event.entity)
+                    .insert(OpenAuthModAuthResult(Err(error)));
                 continue;
-            };
+            }
 
-            let client = Client::new();
-            let token = access_token.lock().clone();
-            let uuid = account.uuid_or_offline();
+            let client = plugin.client.clone();
             let account = account.clone();
+            let policy = plugin.auth_policy;
             let transaction_id = packet.transaction_id;
             let tx = queue.tx.clone();
+            let pending = plugin.pending_auth_tasks.start();
 
-            let _handle = tokio::spawn(async move {
-                let result = match join_with_server_id_hash(&client, &token, &uuid, &hash).await {
-                    Ok(()) => Ok(()), /* Successfully Authenticated */
-                    Err(InvalidSession | ForbiddenOperation) => {
-                        if let Err(error) = account.refresh().await {
-                            error!("Failed to refresh account: {error}");
-                            return;
-                        }
-
-                        /* Retry after refreshing */
-                        join_with_server_id_hash(&client, &token, &uuid, &hash).await
-                    }
-                    Err(error) => Err(error),
-                };
+            let handle = spawn_on(plugin.runtime.as_ref(), async move {
+                let _pending = pending;
+                let result = authenticate(&client, &account, &hash, policy).await;
 
                 /* Send directly instead of SendLoginPacketEvent because of lifetimes */
                 let _ = tx.send(ServerboundLoginPacket::CustomQueryAnswer(
@@ -208,40 +463,287 @@ impl ViaVersionPlugin {
                         data: Some(vec![u8::from(result.is_ok())].into()),
                     },
                 ));
+
+                result
             });
+
+            commands
+                .entity(event.entity)
+                .insert(OpenAuthModJoinTask::new(handle));
         }
     }
 }
 
+/// Spawn a task on `runtime`, or the current tokio runtime if there isn't one.
+pub(crate) fn spawn_on<F>(runtime: Option<&Handle>, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match runtime {
+        Some(runtime) => runtime.spawn(future),
+        None => tokio::spawn(future),
+    }
+}
+
+/// Where [`route_through_proxy`] last pointed the swarm's address.
+#[derive(Debug)]
+pub(crate) struct Route {
+    /// The host it wrote, to tell whether the address is still the routed one.
+    encoded_host: String,
+    /// The server the host leads to, resolved already with `resolve_target`.
+    target: ServerAddress,
+    /// The ViaProxy address it wrote as the resolved address.
+    proxy_addr: SocketAddr,
+}
+
+/// Point an address at ViaProxy, returning whether it had to be changed.
+///
+/// An address without a host hasn't been set yet, so it's left alone until it is. With
+/// `resolve_target`, ViaProxy is given the IP azalea resolved instead of the host. An address
+/// that's still the routed one is re-encoded from the target in `route`, so a version or port
+/// change never mistakes the old ViaProxy address for the server.
+fn route_through_proxy(
+    address: &mut ServerAddress,
+    resolved_address: &mut SocketAddr,
+    route: &mut Option<Route>,
+    proxy_addr: SocketAddr,
+    host_literal: &str,
+    version: &str,
+    resolve_target: bool,
+) -> bool {
+    if address.host.is_empty() {
+        return false;
+    }
+
+    let target = match route.as_ref() {
+        Some(route) if address.host == route.encoded_host => route.target.clone(),
+        /* Only the host was reset, so there's no resolved address for it */
+        Some(route) if *resolved_address == route.proxy_addr => address.clone(),
+        /* Until it's routed, the resolved address is where azalea resolved the target to */
+        _ if resolve_target && *resolved_address != proxy_addr => ServerAddress {
+            host: resolved_target(&address.host, resolved_address.ip()),
+            port: resolved_address.port(),
+        },
+        _ => address.clone(),
+    };
+    let host = encode_host(&target.host, target.port, host_literal, version);
+    let changed = address.host != host || *resolved_address != proxy_addr;
+
+    address.host.clone_from(&host);
+    *resolved_address = proxy_addr;
+    *route = Some(Route {
+        encoded_host: host,
+        target,
+        proxy_addr,
+    });
+
+    changed
+}
+
+/// Replace the host with the IP it resolved to, keeping any forwarding data.
+fn resolved_target(host: &str, resolved_ip: IpAddr) -> String {
+    match host.split_once('\0') {
+        Some((_, data)) => format!("{resolved_ip}\0{data}"),
+        None => resolved_ip.to_string(),
+    }
+}
+
+/// [`encode_via_host`] for a host that may already be encoded or carry forwarding data.
+///
+/// Hosts that are already encoded are re-encoded with `version` instead of being wrapped twice,
+/// keeping their target as it is, and any forwarding data after a null byte is kept at the end.
+fn encode_host(host: &str, port: u16, host_literal: &str, version: &str) -> String {
+    let (host, data_after_null_byte) = match host.split_once('\0') {
+        Some((host, data)) => (host, Some(data)),
+        None => (host, None),
+    };
+
+    // sadly, the first part of the resolved address is unused as viaproxy will resolve it on its own
+    // more info: https://github.com/ViaVersion/ViaProxy/issues/338
+    match host.split('\x07').nth(1) {
+        Some(target) => {
+            let mut connection_host = format!("{host_literal}\x07{target}\x07{version}");
+            if let Some(data) = data_after_null_byte {
+                connection_host.push('\0');
+                connection_host.push_str(data);
+            }
+
+            connection_host
+        }
+        None => encode_via_host(host_literal, host, port, version, data_after_null_byte),
+    }
+}
+
+/// Encode a target server into the host ViaProxy's `INTERNAL` wildcard domain handling expects,
+/// like `127.0.0.1\x07example.com\x071.8`.
+///
+/// `host_literal` is the address bots reach ViaProxy at, which ViaProxy ignores. The port is left
+/// out when it's the default 25565, so ViaProxy still looks up SRV records, and IPv6 hosts are put
+/// in brackets when it's added. `extra_forwarding` is data like Forge's marker, which is kept
+/// after a null byte.
+#[must_use]
+pub fn encode_via_host(
+    host_literal: &str,
+    target_host: &str,
+    target_port: u16,
+    version: &str,
+    extra_forwarding: Option<&str>,
+) -> String {
+    let target = match (target_port, target_host.parse::<std::net::Ipv6Addr>()) {
+        (25565, _) => target_host.to_owned(),
+        (port, Ok(ip)) => SocketAddr::from((ip, port)).to_string(),
+        (port, Err(_)) => format!("{target_host}:{port}"),
+    };
+
+    let mut connection_host = format!("{host_literal}\x07{target}\x07{version}");
+    if let Some(data) = extra_forwarding {
+        connection_host.push('\0');
+        connection_host.push_str(data);
+    }
+
+    connection_host
+}
+
+/// The exact address bots reach ViaProxy at, so the host doesn't depend on how `localhost` resolves.
+fn default_host_literal(connect_addr: SocketAddr) -> String {
+    connect_addr.ip().to_string()
+}
+
 /// Try to find the system's Java version.
 ///
 /// This uses `-version` and `stderr`, because it's backwards compatible.
 ///
 /// # Errors
-/// Will return `Err` if `Version::parse` fails.
+/// Will return `Err` with [`ViaStartError::UnrecognizedJava`] if `java` runs but its output isn't
+/// a Java version.
 ///
 /// # Options
 /// Will return `None` if java is not found.
 pub async fn try_find_java_version() -> Result<Option<Version>> {
-    Ok(match Command::new("java").arg("-version").output().await {
+    try_find_java_version_at("java").await
+}
+
+/// [`try_find_java_version`], but running this `java` instead of the one on `PATH`.
+///
+/// # Errors
+/// Will return `Err` with [`ViaStartError::UnrecognizedJava`] if `java` runs but its output isn't
+/// a Java version.
+///
+/// # Options
+/// Will return `None` if `java` doesn't exist or can't be run.
+pub async fn try_find_java_version_at(java: impl AsRef<OsStr>) -> Result<Option<Version>> {
+    Ok(match Command::new(java).arg("-version").output().await {
         Err(_) => None, /* Java not found */
         Ok(output) => {
-            let stderr = String::from_utf8(output.stderr).context("UTF-8")?;
-            Some(parse_java_version(&stderr)?)
+            let mut stderr = String::from_utf8(output.stderr).context("UTF-8")?;
+            if stderr.trim().is_empty() {
+                /* Some wrappers and shims print to stdout instead */
+                stderr = String::from_utf8_lossy(&output.stdout).into_owned();
+            }
+
+            let version = parse_java_version(&stderr)?;
+            if let Some(warning) = java_warning(&version, &stderr) {
+                warn!("{warning}");
+            }
+
+            Some(version)
         }
     })
 }
 
-fn parse_java_version(stderr: &str) -> Result<Version> {
-    // whole, first group, second group
-    let (_, major, mut minor_patch) =
-        regex_captures!(r"(\d+)(\.\d+\.\d+)?", stderr).context("Regex")?;
-    if minor_patch.is_empty() {
-        minor_patch = ".0.0";
+/// Find the `java` to run ViaProxy with, along with its version.
+///
+/// A given `java` is the only one tried. Otherwise `java` on `PATH` is tried first, then the one in
+/// `JAVA_HOME`, so systems without Java on `PATH` still work.
+pub(crate) async fn find_java(java: Option<&Path>) -> Result<Option<(PathBuf, Version)>> {
+    let candidates = match java {
+        Some(java) => vec![java.to_owned()],
+        None => std::iter::once(PathBuf::from("java"))
+            .chain(std::env::var_os("JAVA_HOME").map(|java_home| {
+                Path::new(&java_home)
+                    .join("bin")
+                    .join(format!("java{EXE_SUFFIX}"))
+            }))
+            .collect(),
+    };
+
+    for java in candidates {
+        if let Some(version) = try_find_java_version_at(&java).await? {
+            return Ok(Some((absolute_program(java), version)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Make the path of a program absolute, so ViaProxy runs the one that was checked even though it's
+/// spawned in the working dir, and without the app's environment.
+///
+/// Bare names like `java` are found on the app's `PATH`, and are kept as they are if they aren't
+/// on it. Relative paths like `jdk/bin/java` are resolved from the app's current directory.
+fn absolute_program(program: PathBuf) -> PathBuf {
+    if program.components().count() != 1 {
+        return std::path::absolute(&program).unwrap_or(program);
     }
+    let Some(paths) = std::env::var_os("PATH") else {
+        return program;
+    };
 
-    let text = format!("{major}{minor_patch}");
-    Ok(Version::parse(&text)?)
+    let mut name = program.clone().into_os_string();
+    if program.extension().is_none() {
+        name.push(EXE_SUFFIX);
+    }
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(&name))
+        .find(|path| path.is_absolute() && path.is_file())
+        .unwrap_or(program)
+}
+
+/// Warn about Java versions that usually work, but that ViaProxy isn't tested on.
+fn java_warning(version: &Version, stderr: &str) -> Option<String> {
+    let suggestion = format!("consider an LTS release like Java {JAVA_LTS_VERSION}");
+    if quoted_java_version(stderr).is_some_and(|quoted| quoted.ends_with("-ea")) {
+        Some(format!(
+            "Java {version} is an early access build, {suggestion}"
+        ))
+    } else if version.major > JAVA_TESTED_VERSION {
+        Some(format!(
+            "Java {version} is newer than ViaProxy has been tested on, {suggestion}"
+        ))
+    } else {
+        None
+    }
+}
+
+/// The quoted version in `java -version`'s output, like `21.0.5` or `24-ea`.
+///
+/// Only the quoted version is read, since banners and lines like `Picked up JAVA_TOOL_OPTIONS`
+/// have other numbers and flags in them.
+fn quoted_java_version(stderr: &str) -> Option<&str> {
+    regex_captures!(r#"version "([^"]*)""#, stderr).map(|(_, quoted)| quoted)
+}
+
+fn parse_java_version(stderr: &str) -> Result<Version> {
+    let unrecognized = || ViaStartError::UnrecognizedJava {
+        output: stderr.trim().to_owned(),
+    };
+
+    let quoted = quoted_java_version(stderr).ok_or_else(unrecognized)?;
+
+    /* Versions like `21-ea`, `17.0.9+9`, `1.8.0_432` or `11.0.9.1` are cut at the first character
+     * that isn't a digit or a dot, and only the first three numbers are kept */
+    let numbers = quoted
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()
+        .unwrap_or_default();
+    let mut parts = numbers.split('.').map(str::parse::<u64>);
+    let major = parts.next().and_then(Result::ok).ok_or_else(unrecognized)?;
+    let mut next = || parts.next().and_then(Result::ok).unwrap_or(0);
+    let minor = next();
+    let patch = next();
+
+    Ok(Version::new(major, minor, patch))
 }
 
 /// Try to find a free port and return the socket address
@@ -251,53 +753,309 @@ fn parse_java_version(stderr: &str) -> Result<Version> {
 /// # Errors
 /// Will return `Err` if `TcpListener::bind` or `TcpListener::local_addr` fails.
 pub async fn try_find_free_addr() -> Result<SocketAddr> {
-    Ok(TcpListener::bind("127.0.0.1:0").await?.local_addr()?)
+    find_free_addr(Ipv4Addr::LOCALHOST.into()).await
 }
 
-/// Try to download and save a file if it doesn't exist.
-///
-/// # Errors
-/// Will return `Err` if the file fails to download or save.
-pub async fn try_download_file<U, P>(url: U, dir: P, file: &str) -> Result<()>
-where
-    U: IntoUrl + Send + Sync,
-    P: AsRef<Path> + Send + Sync,
-{
-    tokio::fs::create_dir_all(&dir).await?;
-    let path = dir.as_ref().join(file);
-    if path.exists() {
-        return Ok(());
-    }
+/// [`try_find_free_addr`], on another interface.
+pub(crate) async fn find_free_addr(ip: IpAddr) -> Result<SocketAddr> {
+    Ok(reserve_free_addr(ip)?.addr)
+}
 
-    let response = reqwest::get(url).await?;
-    let mut pb = tqdm!(
-        total = usize::try_from(response.content_length().unwrap_or(0))?,
-        unit_scale = true,
-        unit_divisor = 1024,
-        unit = "B",
-        force_refresh = true
-    );
+/// A free port that's kept bound until right before ViaProxy is spawned, so other processes can't
+/// take it in the meantime.
+///
+/// The JVM can't take over a listening socket from its parent on any platform, so ViaProxy still
+/// binds the port itself and a small window remains, which is covered by
+/// [`ViaVersionBuilder::with_bind_retries`]. On Unix the socket uses `SO_REUSEADDR`, so ViaProxy
+/// can bind the port as soon as it's released. It isn't set on Windows, where it would let other
+/// processes bind the port while it's reserved.
+pub(crate) struct ReservedAddr {
+    pub(crate) addr: SocketAddr,
+    pub(crate) listener: TcpListener,
+}
 
-    pb.write(format!("Downloading {file}"))?;
+pub(crate) fn reserve_free_addr(ip: IpAddr) -> Result<ReservedAddr> {
+    reserve_addr(SocketAddr::new(ip, 0))
+}
 
-    let mut file = File::create(path).await?;
-    let mut stream = response.bytes_stream();
+/// Reserve `addr`, or a free port on its interface if its port is 0.
+///
+/// # Errors
+/// Will return `Err` with [`ViaStartError::PortInUse`] if something else listens on `addr`.
+pub(crate) fn reserve_addr(addr: SocketAddr) -> Result<ReservedAddr> {
+    let socket = match addr.ip() {
+        IpAddr::V4(_) => TcpSocket::new_v4()?,
+        IpAddr::V6(_) => TcpSocket::new_v6()?,
+    };
 
-    while let Some(item) = stream.next().await {
-        let chunk = item?;
-        file.write_all(&chunk).await?;
-        pb.update(chunk.len())?;
-    }
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
 
-    pb.refresh()?;
+    socket.bind(addr).map_err(|error| -> anyhow::Error {
+        if error.kind() == std::io::ErrorKind::AddrInUse {
+            ViaStartError::PortInUse { addr }.into()
+        } else {
+            error.into()
+        }
+    })?;
+    let listener = socket.listen(1)?;
 
-    Ok(())
+    Ok(ReservedAddr {
+        addr: listener.local_addr()?,
+        listener,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_encode_host() {
+        let host = encode_host("example.com", 25565, "localhost", "1.8");
+        assert_eq!(host, "localhost\x07example.com\x071.8");
+    }
+
+    #[test]
+    fn test_encode_host_single_separator() {
+        let host = encode_host("localhost\x07example.com", 25565, "localhost", "1.8");
+        assert_eq!(host, "localhost\x07example.com\x071.8");
+    }
+
+    #[test]
+    fn test_encode_host_already_encoded() {
+        let host = encode_host(
+            "localhost\x07example.com\x071.21.4",
+            25565,
+            "localhost",
+            "1.8",
+        );
+        assert_eq!(host, "localhost\x07example.com\x071.8");
+        assert_eq!(encode_host(&host, 25565, "localhost", "1.8"), host);
+    }
+
+    #[test]
+    fn test_encode_host_forwarding_data() {
+        let host = encode_host("example.com\0FML\0", 25565, "localhost", "1.8");
+        assert_eq!(host, "localhost\x07example.com\x071.8\0FML\0");
+        assert_eq!(encode_host(&host, 25565, "localhost", "1.8"), host);
+    }
+
+    #[test]
+    fn test_encode_via_host() {
+        let host = encode_via_host("127.0.0.1", "example.com", 25565, "1.8", None);
+        assert_eq!(host, "127.0.0.1\x07example.com\x071.8");
+        let host = encode_via_host("127.0.0.1", "example.com", 25566, "1.8", None);
+        assert_eq!(host, "127.0.0.1\x07example.com:25566\x071.8");
+    }
+
+    #[test]
+    fn test_encode_via_host_forwarding_data() {
+        let host = encode_via_host("127.0.0.1", "example.com", 25566, "1.8", Some("FML\0"));
+        assert_eq!(host, "127.0.0.1\x07example.com:25566\x071.8\0FML\0");
+    }
+
+    #[test]
+    fn test_encode_via_host_ipv6() {
+        let host = encode_via_host("::1", "2001:db8::1", 25565, "1.8", None);
+        assert_eq!(host, "::1\x072001:db8::1\x071.8");
+        let host = encode_via_host("::1", "2001:db8::1", 25566, "1.8", None);
+        assert_eq!(host, "::1\x07[2001:db8::1]:25566\x071.8");
+    }
+
+    #[test]
+    fn test_encode_host_keeps_encoded_port() {
+        let host = encode_host("example.com", 25566, "localhost", "1.8");
+        assert_eq!(host, "localhost\x07example.com:25566\x071.8");
+        assert_eq!(encode_host(&host, 25566, "localhost", "1.8"), host);
+    }
+
+    #[test]
+    fn test_host_literal_ipv4() {
+        let bind_addr = SocketAddr::from(([127, 0, 0, 1], 25566));
+        let host = encode_host(
+            "example.com",
+            25565,
+            &default_host_literal(bind_addr),
+            "1.8",
+        );
+        assert_eq!(host, "127.0.0.1\x07example.com\x071.8");
+    }
+
+    #[test]
+    fn test_host_literal_ipv6() {
+        let bind_addr = SocketAddr::from((std::net::Ipv6Addr::LOCALHOST, 25566));
+        let host = encode_host(
+            "example.com",
+            25565,
+            &default_host_literal(bind_addr),
+            "1.8",
+        );
+        assert_eq!(host, "::1\x07example.com\x071.8");
+    }
+
+    #[test]
+    fn test_route_through_proxy_after_reconnect() {
+        let mut route = None;
+        let bind_addr = SocketAddr::from(([127, 0, 0, 1], 25566));
+        let original = ServerAddress {
+            host: "example.com".to_owned(),
+            port: 25565,
+        };
+        let original_resolved = SocketAddr::from(([192, 0, 2, 1], 25565));
+
+        let mut address = original.clone();
+        let mut resolved_address = original_resolved;
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            false
+        ));
+        assert!(!route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            false
+        ));
+
+        /* Simulate azalea resetting the address when reconnecting */
+        address = original;
+        resolved_address = original_resolved;
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            false
+        ));
+        assert_eq!(address.host, "localhost\x07example.com\x071.8");
+        assert_eq!(address.port, 25565);
+        assert_eq!(resolved_address, bind_addr);
+    }
+
+    #[test]
+    fn test_route_through_proxy_address_set_after_startup() {
+        let mut route = None;
+        let bind_addr = SocketAddr::from(([127, 0, 0, 1], 25566));
+        let unresolved = SocketAddr::from(([0, 0, 0, 0], 0));
+
+        /* The swarm's address isn't known yet at startup */
+        let mut address = ServerAddress {
+            host: String::new(),
+            port: 25565,
+        };
+        let mut resolved_address = unresolved;
+        assert!(!route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            false
+        ));
+        assert_eq!(address.host, "");
+        assert_eq!(resolved_address, unresolved);
+
+        address.host = "example.com".to_owned();
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            false
+        ));
+        assert_eq!(address.host, "localhost\x07example.com\x071.8");
+        assert_eq!(resolved_address, bind_addr);
+    }
+
+    #[test]
+    fn test_route_through_proxy_resolved_target() {
+        let mut route = None;
+        let bind_addr = SocketAddr::from(([127, 0, 0, 1], 25566));
+        let mut address = ServerAddress {
+            host: "example.com\0FML\0".to_owned(),
+            port: 25565,
+        };
+        let mut resolved_address = SocketAddr::from(([192, 0, 2, 1], 25565));
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            true
+        ));
+        assert_eq!(address.host, "localhost\x07192.0.2.1\x071.8\0FML\0");
+
+        /* Later updates keep the IP instead of resolving the proxy's own address */
+        assert!(!route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            bind_addr,
+            "localhost",
+            "1.8",
+            true
+        ));
+    }
+
+    #[test]
+    fn test_route_through_proxy_after_proxy_moved() {
+        let old_addr = SocketAddr::from(([127, 0, 0, 1], 25566));
+        let new_addr = SocketAddr::from(([127, 0, 0, 1], 25567));
+        let mut route = None;
+        let mut address = ServerAddress {
+            host: "example.com".to_owned(),
+            port: 25565,
+        };
+        let mut resolved_address = SocketAddr::from(([192, 0, 2, 1], 25570));
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            old_addr,
+            "127.0.0.1",
+            "1.8",
+            true
+        ));
+
+        /* A restart moved ViaProxy, so the resolved address is the old ViaProxy port */
+        assert!(route_through_proxy(
+            &mut address,
+            &mut resolved_address,
+            &mut route,
+            new_addr,
+            "127.0.0.1",
+            "1.8",
+            true
+        ));
+        assert_eq!(address.host, "127.0.0.1\x07192.0.2.1:25570\x071.8");
+        assert_eq!(resolved_address, new_addr);
+    }
+
+    #[test]
+    fn test_parse_unrecognized_java() {
+        let stderr = "pyenv: java: command not found";
+        let error = parse_java_version(stderr).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ViaStartError>(),
+            Some(ViaStartError::UnrecognizedJava { output }) if output == stderr
+        ));
+    }
+
     #[test]
     fn test_parse_openjdk_ea() {
         let stderr = "openjdk version \"24-ea\" 2025-03-18
@@ -308,6 +1066,50 @@ OpenJDK 64-Bit Server VM (build 24-ea+29-3578, mixed mode, sharing)"
         assert_eq!(version, Version::new(24, 0, 0));
     }
 
+    #[test]
+    fn test_java_warning() {
+        let ea = "openjdk version \"24-ea\" 2025-03-18";
+        assert!(java_warning(&Version::new(24, 0, 0), ea).is_some());
+        let future = "openjdk version \"30\" 2028-03-21";
+        assert!(java_warning(&Version::new(30, 0, 0), future).is_some());
+        let lts = "openjdk version \"21.0.5\" 2024-10-15 LTS";
+        assert!(java_warning(&Version::new(21, 0, 5), lts).is_none());
+        let assertions =
+            "Picked up JAVA_TOOL_OPTIONS: -ea\nopenjdk version \"21.0.5\" 2024-10-15 LTS";
+        assert!(java_warning(&Version::new(21, 0, 5), assertions).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_java_only_tries_given_path() {
+        let java = Path::new("/nonexistent/bin/java");
+        assert!(find_java(Some(java)).await.unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_absolute_program() {
+        assert!(absolute_program(PathBuf::from("sh")).is_absolute());
+        let java = PathBuf::from("/nonexistent/bin/java");
+        assert_eq!(absolute_program(java.clone()), java);
+        let missing = PathBuf::from("azalea-viaversion-missing");
+        assert_eq!(absolute_program(missing.clone()), missing);
+        let relative = absolute_program(PathBuf::from("jdk/bin/java"));
+        assert_eq!(
+            relative,
+            std::env::current_dir().unwrap().join("jdk/bin/java")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reserve_addr_in_use() {
+        let reserved = reserve_free_addr(Ipv4Addr::LOCALHOST.into()).unwrap();
+        let error = reserve_addr(reserved.addr).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<ViaStartError>(),
+            Some(&ViaStartError::PortInUse { addr }) if addr == reserved.addr
+        ));
+    }
+
     #[test]
     fn test_parse_openjdk_8() {
         let stderr = "openjdk version \"1.8.0_432\"
@@ -327,4 +1129,66 @@ OpenJDK 64-Bit Server VM (build 11.0.25+9, mixed mode)"
         let version = parse_java_version(&stderr).unwrap();
         assert_eq!(version, Version::new(11, 0, 25));
     }
+
+    #[test]
+    fn test_parse_openjdk_21_without_dots() {
+        let stderr = "openjdk version \"21\" 2023-09-19
+OpenJDK Runtime Environment (build 21+35-2513)
+OpenJDK 64-Bit Server VM (build 21+35-2513, mixed mode, sharing)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(21, 0, 0));
+    }
+
+    #[test]
+    fn test_parse_java_after_other_numbers() {
+        let stderr = "Picked up JAVA_TOOL_OPTIONS: -Xmx2048m -Dfile.encoding=UTF-8
+openjdk version \"17.0.9\" 2023-10-17
+OpenJDK Runtime Environment Temurin-17.0.9+9 (build 17.0.9+9)
+OpenJDK 64-Bit Server VM Temurin-17.0.9+9 (build 17.0.9+9, mixed mode, sharing)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(17, 0, 9));
+    }
+
+    #[test]
+    fn test_parse_java_with_build_number() {
+        let stderr = "openjdk version \"17.0.9+9\" 2023-10-17
+OpenJDK Runtime Environment Temurin-17.0.9+9 (build 17.0.9+9)
+OpenJDK 64-Bit Server VM Temurin-17.0.9+9 (build 17.0.9+9, mixed mode, sharing)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(17, 0, 9));
+    }
+
+    #[test]
+    fn test_parse_zulu_8() {
+        let stderr = "openjdk version \"1.8.0_432\"
+OpenJDK Runtime Environment (Zulu 8.82.0.21-CA-linux64) (build 1.8.0_432-b06)
+OpenJDK 64-Bit Server VM (Zulu 8.82.0.21-CA-linux64) (build 25.432-b06, mixed mode)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(1, 8, 0));
+    }
+
+    #[test]
+    fn test_parse_graalvm_ce() {
+        let stderr = "openjdk version \"21.0.2\" 2024-01-16
+OpenJDK Runtime Environment GraalVM CE 21.0.2+13.1 (build 21.0.2+13-jvmci-23.1-b30)
+OpenJDK 64-Bit Server VM GraalVM CE 21.0.2+13.1 (build 21.0.2+13-jvmci-23.1-b30, mixed mode, sharing)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(21, 0, 2));
+    }
+
+    #[test]
+    fn test_parse_corretto() {
+        let stderr = "openjdk version \"17.0.13\" 2024-10-15 LTS
+OpenJDK Runtime Environment Corretto-17.0.13.11.1 (build 17.0.13+11-LTS)
+OpenJDK 64-Bit Server VM Corretto-17.0.13.11.1 (build 17.0.13+11-LTS, mixed mode, sharing)";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(17, 0, 13));
+    }
+
+    #[test]
+    fn test_parse_java_four_numbers() {
+        let stderr = "openjdk version \"11.0.9.1\" 2020-11-04";
+        let version = parse_java_version(stderr).unwrap();
+        assert_eq!(version, Version::new(11, 0, 9));
+    }
 }
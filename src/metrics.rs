@@ -0,0 +1,44 @@
+#[cfg(feature = "metrics")]
+use ::metrics::{counter, gauge};
+#[cfg(feature = "metrics")]
+use azalea::{ecs::prelude::*, Account};
+
+/// Count a file that finished downloading.
+pub(crate) fn record_download(file: &str) {
+    #[cfg(feature = "metrics")]
+    counter!("azalea_viaversion_downloads_total", "file" => file.to_owned()).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = file;
+}
+
+/// Count a finished OpenAuthMod join.
+pub(crate) fn record_auth(success: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let result = if success { "success" } else { "failure" };
+        counter!("azalea_viaversion_auth_total", "result" => result).increment(1);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = success;
+}
+
+/// Count ViaProxy being restarted by a [`ViaController`](crate::ViaController).
+pub(crate) fn record_restart() {
+    #[cfg(feature = "metrics")]
+    counter!("azalea_viaversion_restarts_total").increment(1);
+}
+
+/// Count a warning from one of the Via translation layers, including ones the buffer dropped.
+pub(crate) fn record_translation_warning() {
+    #[cfg(feature = "metrics")]
+    counter!("azalea_viaversion_translation_warnings_total").increment(1);
+}
+
+/// Report how many bots are connecting through ViaProxy.
+///
+/// Every bot in the swarm is routed through ViaProxy, so this counts every bot.
+#[cfg(feature = "metrics")]
+#[allow(clippy::needless_pass_by_value, clippy::cast_precision_loss)]
+pub(crate) fn record_connections(query: Query<(), With<Account>>) {
+    gauge!("azalea_viaversion_active_connections").set(query.iter().count() as f64);
+}
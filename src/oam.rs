@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use azalea::buf::AzaleaRead;
+use std::io::Cursor;
+
+/// A custom login query sent by the OpenAuthMod addon.
+///
+/// Only [`OpenAuthModRequest::Join`] is answered by the plugin. The others are only recognized so
+/// they can be logged, and are left to azalea, which answers that it doesn't understand them.
+/// Answering `oam:sign_nonce` would need the account's chat signing key, which the plugin doesn't
+/// fetch, so servers that need it can't be joined yet.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum OpenAuthModRequest {
+    /// `oam:join`, asking the client to join the server with Mojang's session server.
+    Join { server_id_hash: String },
+    /// `oam:sign_nonce`, asking the client to sign a nonce with its chat signing key. Not answered.
+    SignNonce,
+    /// `oam:data`, asking the client for OpenAuthMod's own data. Not answered.
+    Data,
+}
+
+impl OpenAuthModRequest {
+    /// Parse a custom query, returning `None` if it isn't from OpenAuthMod.
+    ///
+    /// Identifiers are matched ignoring case, and under OpenAuthMod's full name as a namespace
+    /// too, so a rename in a later version of the addon doesn't silently skip the auth.
+    ///
+    /// # Errors
+    /// Will return `Err` if the query is from OpenAuthMod but its data is malformed.
+    pub(crate) fn parse(identifier: &str, data: &[u8]) -> Result<Option<Self>> {
+        let identifier = identifier.to_ascii_lowercase();
+        let Some((namespace, path)) = identifier.split_once(':') else {
+            return Ok(None);
+        };
+        if !matches!(namespace, "oam" | "openauthmod") {
+            return Ok(None);
+        }
+
+        let request = match path {
+            "join" => {
+                let server_id_hash = String::azalea_read(&mut Cursor::new(data))
+                    .context("Failed to read server id hash from oam:join packet")?;
+                Self::Join { server_id_hash }
+            }
+            "sign_nonce" => Self::SignNonce,
+            "data" => Self::Data,
+            _ => return Ok(None),
+        };
+
+        Ok(Some(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_join() {
+        /* A VarInt length followed by the UTF-8 hash */
+        let data = [&[4][..], b"abcd"].concat();
+        let request = OpenAuthModRequest::parse("oam:join", &data).unwrap();
+        assert_eq!(
+            request,
+            Some(OpenAuthModRequest::Join {
+                server_id_hash: "abcd".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_join_other_casing() {
+        let data = [&[4][..], b"abcd"].concat();
+        for identifier in ["OAM:Join", "OpenAuthMod:join"] {
+            let request = OpenAuthModRequest::parse(identifier, &data).unwrap();
+            assert!(matches!(request, Some(OpenAuthModRequest::Join { .. })));
+        }
+    }
+
+    #[test]
+    fn test_parse_other_requests() {
+        let request = OpenAuthModRequest::parse("oam:sign_nonce", &[]).unwrap();
+        assert_eq!(request, Some(OpenAuthModRequest::SignNonce));
+        assert_eq!(
+            OpenAuthModRequest::parse("velocity:player_info", &[]).unwrap(),
+            None
+        );
+        assert!(OpenAuthModRequest::parse("oam:join", &[]).is_err());
+    }
+}
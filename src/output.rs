@@ -0,0 +1,825 @@
+use anyhow::{Context, Result};
+use lazy_regex::{regex, regex_captures};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt},
+    sync::{mpsc, oneshot, watch},
+};
+use tracing::{debug, error, trace, warn};
+
+use crate::{metrics, ViaStartupReport};
+
+/// How long a bind failure may take to show up after ViaProxy says it's binding.
+const BIND_RESULT_GRACE: Duration = Duration::from_millis(500);
+
+/// Reads ViaProxy's output, logging it and picking out the lines the plugin cares about.
+pub(crate) struct OutputReader {
+    pub(crate) report: Arc<RwLock<ViaStartupReport>>,
+    pub(crate) via_debug: bool,
+    pub(crate) warnings_tx: mpsc::Sender<String>,
+    pub(crate) ready_marker: &'static str,
+    /// Whether the marker is printed before ViaProxy binds its port, so the reader waits
+    /// [`BIND_RESULT_GRACE`] for a bind failure before sending `ready_tx`.
+    pub(crate) ready_before_bind: bool,
+    pub(crate) ready_tx: watch::Sender<()>,
+    pub(crate) first_output_tx: Option<oneshot::Sender<()>>,
+    /// The major version of the `java` we detected, which picked the jar.
+    pub(crate) java_major: u64,
+    /// Set if ViaProxy failed to bind its address, which stops the reader.
+    pub(crate) bind_failed: Arc<AtomicBool>,
+    /// Set when the plugin stops ViaProxy itself, so exiting isn't warned about.
+    pub(crate) exit_expected: Arc<AtomicBool>,
+    /// The first `FATAL` message ViaProxy printed before it was ready, which stops the reader.
+    pub(crate) fatal_error: Arc<Mutex<Option<String>>>,
+    /// The last lines ViaProxy printed, attached to the error if it fails to start.
+    pub(crate) recent_output: Arc<Mutex<RecentOutput>>,
+    /// Where every line is also written, set with
+    /// [`ViaVersionBuilder::with_log_file`](crate::ViaVersionBuilder::with_log_file).
+    pub(crate) log_file: Option<Arc<Mutex<LogFile>>>,
+}
+
+impl OutputReader {
+    /// Read lines until ViaProxy exits or its output fails to read.
+    pub(crate) async fn run<R: AsyncBufRead + Unpin>(mut self, mut reader: R) {
+        let mut buf = Vec::new();
+        let mut ready = false;
+        let mut bind_pending = false;
+
+        loop {
+            buf.clear();
+            let result = loop {
+                let read = reader.read_until(b'\n', &mut buf);
+                if !bind_pending {
+                    break read.await;
+                }
+
+                /* Anything read before the timeout stays in `buf`, so reading just continues */
+                match tokio::time::timeout(BIND_RESULT_GRACE, read).await {
+                    Ok(result) => break result,
+                    Err(_) => {
+                        /* No bind failure in time, so the port is bound */
+                        bind_pending = false;
+                        ready = true;
+                        let _ = self.ready_tx.send(());
+                    }
+                }
+            };
+            match result {
+                Ok(0) => {
+                    /* Exiting before it's ready is reported by the startup instead */
+                    if ready && !self.exit_expected.load(Ordering::Relaxed) {
+                        warn!("ViaProxy exited, bots can't connect until it's restarted");
+                    }
+                    break;
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!("Failed to read ViaProxy's output, it won't be logged anymore: {error}");
+                    break;
+                }
+            }
+
+            if let Some(first_output_tx) = self.first_output_tx.take() {
+                let _ = first_output_tx.send(());
+            }
+
+            let line = String::from_utf8_lossy(&buf);
+            let line = strip_ansi(&line);
+            /* Lines from before log4j is set up, or without structured logs, are parsed as is */
+            let line = parse_structured_record(&line).map_or(line, Cow::Owned);
+            if self.via_debug {
+                debug!("{}", line.trim());
+            } else {
+                trace!("{}", line.trim());
+            }
+            self.recent_output.lock().push(&line);
+            if let Some(log_file) = &self.log_file {
+                log_file.lock().write_line(&line);
+            }
+
+            if let Some(version) = parse_via_proxy_version(&line) {
+                self.report.write().via_proxy_version = Some(version);
+            }
+
+            if let Some((java_version, major)) = parse_java_runtime(&line) {
+                if major != self.java_major {
+                    warn!(
+                        "ViaProxy is running on {java_version}, but Java {} was detected and picked the jar",
+                        self.java_major
+                    );
+                }
+                self.report.write().java_version = Some(java_version);
+            }
+
+            if is_bind_failure(&line) {
+                if !ready {
+                    /* ViaProxy can't do anything without its port, so stop waiting for it */
+                    self.bind_failed.store(true, Ordering::Relaxed);
+                    break;
+                }
+                /* Outbound connections can fail to bind too, like when ephemeral ports run out */
+                warn!("ViaProxy: {}", line.trim());
+            }
+
+            if let Some(message) = parse_fatal_error(&line) {
+                if !ready {
+                    /* ViaProxy exits after a fatal error, so don't wait for it to be ready */
+                    *self.fatal_error.lock() = Some(message);
+                    break;
+                }
+                error!("ViaProxy: {message}");
+            }
+
+            if let Some((component, version)) = parse_via_component(&line) {
+                let mut report = self.report.write();
+                let field = match component {
+                    ViaComponent::ViaBackwards => &mut report.via_backwards_version,
+                    ViaComponent::ViaRewind => &mut report.via_rewind_version,
+                };
+                field.get_or_insert(version);
+            }
+
+            if let Some(address) = parse_bound_address(&line) {
+                self.report.write().bound_address = Some(address);
+            }
+
+            if let Some(detail) = parse_translation_warning(&line) {
+                metrics::record_translation_warning();
+                let _ = self.warnings_tx.try_send(detail);
+            }
+
+            if !ready && line.contains(self.ready_marker) {
+                if self.ready_before_bind {
+                    bind_pending = true;
+                } else {
+                    ready = true;
+                    let _ = self.ready_tx.send(());
+                }
+            }
+        }
+    }
+}
+
+/// Log what ViaProxy writes to stderr, like JVM errors and stack traces, until it exits.
+///
+/// The lines are also kept with the stdout lines, so startup errors include them.
+pub(crate) async fn forward_stderr<R: AsyncBufRead + Unpin>(
+    mut reader: R,
+    recent_output: Arc<Mutex<RecentOutput>>,
+    log_file: Option<Arc<Mutex<LogFile>>>,
+) {
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        if !matches!(reader.read_until(b'\n', &mut buf).await, Ok(1..)) {
+            break; /* ViaProxy exited */
+        }
+
+        let line = String::from_utf8_lossy(&buf);
+        let line = strip_ansi(&line);
+        let line = line.trim_end();
+        if line.contains("Exception") || line.starts_with("Error") {
+            error!("[stderr] {line}");
+        } else {
+            warn!("[stderr] {line}");
+        }
+        let line = format!("[stderr] {line}");
+        recent_output.lock().push(&line);
+        if let Some(log_file) = &log_file {
+            log_file.lock().write_line(&line);
+        }
+    }
+}
+
+/// Remove terminal escape sequences from a line, like colors and the cursor movement ViaProxy's
+/// launcher sometimes prints, so they don't end up in the logs or trip up the parsers.
+pub(crate) fn strip_ansi(line: &str) -> Cow<'_, str> {
+    regex!(r"\x1b\[[0-9;?]*[ -/]*[@-~]").replace_all(line, "")
+}
+
+/// A ring buffer of the last lines ViaProxy printed.
+pub(crate) struct RecentOutput {
+    lines: VecDeque<String>,
+    capacity: usize,
+}
+
+impl RecentOutput {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&mut self, line: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.trim_end().to_owned());
+    }
+
+    /// Add the lines to `error` as context, so printing it with `{:?}` shows what ViaProxy said.
+    ///
+    /// The error can still be downcast to what it was before.
+    pub(crate) fn attach(&self, error: anyhow::Error) -> anyhow::Error {
+        if self.lines.is_empty() {
+            return error;
+        }
+
+        let lines = Vec::from_iter(self.lines.iter().map(String::as_str));
+        error.context(format!(
+            "ViaProxy failed to start, its last {} lines of output were:\n{}",
+            lines.len(),
+            lines.join("\n")
+        ))
+    }
+}
+
+/// A file ViaProxy's output is copied to, which is rotated once it grows past its maximum size.
+pub(crate) struct LogFile {
+    path: PathBuf,
+    /// `None` once writing failed, so the error is only logged once.
+    file: Option<File>,
+    size: u64,
+    max_size: Option<u64>,
+}
+
+impl LogFile {
+    /// Open the file to append to it, creating it if it doesn't exist.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file can't be opened.
+    pub(crate) fn open(path: &Path, max_size: Option<u64>) -> Result<Self> {
+        let file = Self::open_file(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file: Some(file),
+            max_size,
+        })
+    }
+
+    fn open_file(path: &Path) -> io::Result<File> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    pub(crate) fn write_line(&mut self, line: &str) {
+        let line = format!("{}\n", line.trim_end());
+        if let Err(error) = self.write(line.as_bytes()) {
+            warn!(
+                "Failed to write to {}, ViaProxy's output won't be written to it anymore: {error}",
+                self.path.display()
+            );
+            self.file = None;
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+
+        let len = bytes.len() as u64;
+        if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len > max_size)
+        {
+            self.rotate()?;
+        }
+
+        if let Some(file) = &mut self.file {
+            file.write_all(bytes)?;
+            self.size += len;
+        }
+
+        Ok(())
+    }
+
+    /// Move the file to `<path>.1`, replacing the one from the last rotation, and start a new one.
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file = None;
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        std::fs::rename(&self.path, rotated)?;
+        self.file = Some(Self::open_file(&self.path)?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// A log4j2 config that prints every record as a line of JSON, for
+/// [`ViaVersionBuilder::with_structured_logs`](crate::ViaVersionBuilder::with_structured_logs).
+///
+/// `%enc{...}{JSON}` escapes the values, so messages with quotes or newlines stay on one line.
+pub(crate) const STRUCTURED_LOG_CONFIG: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Configuration status="WARN">
+    <Appenders>
+        <Console name="Console" target="SYSTEM_OUT">
+            <PatternLayout pattern="{&quot;time&quot;:&quot;%d{HH:mm:ss}&quot;,&quot;thread&quot;:&quot;%enc{%t}{JSON}&quot;,&quot;level&quot;:&quot;%p&quot;,&quot;logger&quot;:&quot;%enc{%c{1}}{JSON}&quot;,&quot;message&quot;:&quot;%enc{%m}{JSON}&quot;,&quot;thrown&quot;:&quot;%enc{%throwable}{JSON}&quot;}%n"/>
+        </Console>
+    </Appenders>
+    <Loggers>
+        <Root level="info">
+            <AppenderRef ref="Console"/>
+        </Root>
+    </Loggers>
+</Configuration>
+"#;
+
+/// Turn a JSON record from [`STRUCTURED_LOG_CONFIG`] back into the line ViaProxy would print.
+///
+/// The other parsers only rely on the level, source and message, which come from the record's own
+/// fields instead of being picked out of the line. Returns `None` for anything else.
+pub(crate) fn parse_structured_record(line: &str) -> Option<String> {
+    let line = line.trim();
+    if !line.starts_with('{') {
+        return None;
+    }
+
+    let record = serde_json::from_str::<serde_json::Value>(line).ok()?;
+    let field = |name| record.get(name).and_then(serde_json::Value::as_str);
+    let mut text = format!(
+        "[{}] [{}/{}] ({}) {}",
+        field("time").unwrap_or_default(),
+        field("thread").unwrap_or_default(),
+        field("level")?,
+        field("logger").unwrap_or_default(),
+        field("message")?,
+    );
+    if let Some(thrown) = field("thrown").filter(|thrown| !thrown.is_empty()) {
+        text.push(' ');
+        text.push_str(thrown.trim());
+    }
+
+    Some(text)
+}
+
+/// Parse the ViaProxy version and build from its startup banner.
+pub(crate) fn parse_via_proxy_version(line: &str) -> Option<String> {
+    let (_, version, build) = regex_captures!(r"v(\d+\.\d+\.\d+\S*) \((git-[^)]+)\)", line)?;
+    Some(format!("{version} ({build})"))
+}
+
+/// Parse the JVM ViaProxy says it's running on, and its major version.
+///
+/// The version is printed like `java.version`, so Java 8 is `1.8.0_392` while later releases are
+/// like `21.0.3` or `24-ea`.
+pub(crate) fn parse_java_runtime(line: &str) -> Option<(String, u64)> {
+    let (_, java_version) = regex_captures!(r"Using java version: (.+)", line.trim())?;
+    let major = java_version
+        .split_whitespace()
+        .filter(|token| !token.ends_with("-Bit"))
+        .find_map(|token| {
+            let (_, major, minor) = regex_captures!(r"^(\d+)(?:\.(\d+))?", token)?;
+            match (major, minor) {
+                ("1", minor) if !minor.is_empty() => minor.parse().ok(),
+                (major, _) => major.parse().ok(),
+            }
+        })?;
+
+    Some((java_version.to_owned(), major))
+}
+
+/// Parse a Via component that reported its version, like `ViaBackwards 5.2.1 is now loaded`.
+///
+/// This looks for the component's name followed by a version anywhere in the line, so it doesn't
+/// depend on the exact wording of the message.
+pub(crate) fn parse_via_component(line: &str) -> Option<(ViaComponent, String)> {
+    let (_, name, version) = regex_captures!(
+        r"\b(ViaBackwards|ViaRewind)\b[^\d\n]{0,40}?v?(\d+\.\d+(?:\.\d+)?(?:-[\w.]+)?)",
+        line
+    )?;
+    let component = match name {
+        "ViaBackwards" => ViaComponent::ViaBackwards,
+        "ViaRewind" => ViaComponent::ViaRewind,
+        _ => return None,
+    };
+
+    Some((component, version.to_owned()))
+}
+
+/// The Via components whose versions are kept in the [`ViaStartupReport`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ViaComponent {
+    ViaBackwards,
+    ViaRewind,
+}
+
+/// Parse the address ViaProxy says it's listening on, as an address bots can connect to.
+pub(crate) fn parse_bound_address(line: &str) -> Option<SocketAddr> {
+    let (_, address) = regex_captures!(r"Binding proxy server to (\S+:\d+)", line)?;
+    normalize_address(address)
+}
+
+/// Turn the forms Java prints addresses in into a connectable address.
+///
+/// Java prints `hostname/ip:port`, `/ip:port`, or just `hostname:port`, and IPv6 addresses may be
+/// missing their brackets. Wildcard addresses are mapped to loopback, since that's the local hop.
+/// Hostnames other than `localhost` aren't resolved, since that would block the output reader.
+fn normalize_address(address: &str) -> Option<SocketAddr> {
+    let address = address
+        .rsplit_once('/')
+        .map_or(address, |(_, address)| address);
+    let (host, port) = address.rsplit_once(':')?;
+    let port = port.parse().ok()?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    let ip = match host.parse::<IpAddr>() {
+        Ok(ip) => ip,
+        Err(_) if host.eq_ignore_ascii_case("localhost") => Ipv4Addr::LOCALHOST.into(),
+        Err(_) => return None,
+    };
+
+    let ip = match ip {
+        IpAddr::V4(ip) if ip.is_unspecified() => Ipv4Addr::LOCALHOST.into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => Ipv6Addr::LOCALHOST.into(),
+        ip => ip,
+    };
+
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Whether a line is ViaProxy failing to bind its address.
+pub(crate) fn is_bind_failure(line: &str) -> bool {
+    line.contains("java.net.BindException") || line.contains("Address already in use")
+}
+
+/// Parse a `FATAL` log line, returning its message.
+pub(crate) fn parse_fatal_error(line: &str) -> Option<String> {
+    let (_, message) = regex_captures!(r"/FATAL\]\s*(?:\([^)]*\)\s*)?(.+)", line.trim())?;
+    Some(message.to_owned())
+}
+
+/// Parse a warning from one of the Via translation layers, returning its message.
+///
+/// This only relies on the `/WARN]` level marker, an optional `(source)`, and the message
+/// mentioning a packet or coming from a Via platform other than ViaProxy itself.
+pub(crate) fn parse_translation_warning(line: &str) -> Option<String> {
+    let (_, source, detail) = regex_captures!(r"/WARN\]\s*(?:\(([^)]*)\)\s*)?(.+)", line.trim())?;
+    let from_via = source.starts_with("Via") && source != "ViaProxy";
+    let about_packet = detail.to_lowercase().contains("packet");
+
+    (from_via || about_packet).then(|| detail.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ViaStartError;
+
+    #[tokio::test]
+    async fn test_output_reader_backlog() {
+        let mut output = String::new();
+        for i in 0..10_000 {
+            output.push_str(&format!("[12:00:00] [main/INFO] (ViaProxy) Loading {i}\n"));
+        }
+        output.push_str("[12:00:01] [main/INFO] (ViaProxy) Initializing ViaProxy CLI v3.3.7 (git-ViaProxy-3.3.7:0123abc)...\n");
+        output.push_str("[12:00:02] [main/INFO] (ViaVersion) Finished mapping loading\n");
+
+        let report = Arc::<RwLock<ViaStartupReport>>::default();
+        let (warnings_tx, _warnings_rx) = mpsc::channel(1);
+        let (ready_tx, mut ready_rx) = watch::channel(());
+        let reader = OutputReader {
+            report: report.clone(),
+            via_debug: false,
+            warnings_tx,
+            ready_marker: "Finished mapping loading",
+            ready_before_bind: false,
+            ready_tx,
+            first_output_tx: None,
+            java_major: 21,
+            bind_failed: Arc::default(),
+            exit_expected: Arc::default(),
+            fatal_error: Arc::default(),
+            recent_output: Arc::new(Mutex::new(RecentOutput::new(50))),
+            log_file: None,
+        };
+
+        reader
+            .run(tokio::io::BufReader::with_capacity(64, output.as_bytes()))
+            .await;
+        assert!(ready_rx.changed().await.is_ok());
+        assert!(report.read().via_proxy_version.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_output_reader_bind_failure_after_ready() {
+        let output = "[12:00:02] [main/INFO] (ViaVersion) Finished mapping loading
+[12:05:00] [Netty Epoll Child #3/WARN] (ViaProxy) java.net.BindException: Cannot assign requested address
+[12:05:01] [main/INFO] (ViaProxy) Initializing ViaProxy CLI v3.3.7 (git-ViaProxy-3.3.7:0123abc)...
+";
+
+        let report = Arc::<RwLock<ViaStartupReport>>::default();
+        let bind_failed = Arc::<AtomicBool>::default();
+        let (warnings_tx, _warnings_rx) = mpsc::channel(1);
+        let (ready_tx, _ready_rx) = watch::channel(());
+        let reader = OutputReader {
+            report: report.clone(),
+            via_debug: false,
+            warnings_tx,
+            ready_marker: "Finished mapping loading",
+            ready_before_bind: false,
+            ready_tx,
+            first_output_tx: None,
+            java_major: 21,
+            bind_failed: bind_failed.clone(),
+            exit_expected: Arc::default(),
+            fatal_error: Arc::default(),
+            recent_output: Arc::new(Mutex::new(RecentOutput::new(50))),
+            log_file: None,
+        };
+
+        reader.run(output.as_bytes()).await;
+        assert!(!bind_failed.load(Ordering::Relaxed));
+        /* The reader kept going after the failure */
+        assert!(report.read().via_proxy_version.is_some());
+    }
+
+    /// An [`OutputReader`] for the `ProxyBound` milestone, with its bind failure flag and ready
+    /// receiver.
+    fn proxy_bound_reader() -> (OutputReader, Arc<AtomicBool>, watch::Receiver<()>) {
+        let bind_failed = Arc::<AtomicBool>::default();
+        let (warnings_tx, _warnings_rx) = mpsc::channel(1);
+        let (ready_tx, ready_rx) = watch::channel(());
+        let reader = OutputReader {
+            report: Arc::default(),
+            via_debug: false,
+            warnings_tx,
+            ready_marker: "Binding proxy server to",
+            ready_before_bind: true,
+            ready_tx,
+            first_output_tx: None,
+            java_major: 21,
+            bind_failed: bind_failed.clone(),
+            exit_expected: Arc::default(),
+            fatal_error: Arc::default(),
+            recent_output: Arc::new(Mutex::new(RecentOutput::new(50))),
+            log_file: None,
+        };
+
+        (reader, bind_failed, ready_rx)
+    }
+
+    #[tokio::test]
+    async fn test_output_reader_proxy_bound_then_bind_failure() {
+        let output = "[12:00:01] [main/INFO] (ViaProxy) Binding proxy server to 127.0.0.1:25568...
+[12:00:01] [main/ERROR] (ViaProxy) java.net.BindException: Address already in use
+";
+        let (reader, bind_failed, mut ready_rx) = proxy_bound_reader();
+
+        reader.run(output.as_bytes()).await;
+        assert!(bind_failed.load(Ordering::Relaxed));
+        /* The reader stopped without sending, so startup reports the bind failure */
+        assert!(ready_rx.changed().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_output_reader_proxy_bound() {
+        use tokio::io::AsyncWriteExt;
+
+        let (reader, bind_failed, mut ready_rx) = proxy_bound_reader();
+        let (mut writer, output) = tokio::io::duplex(1024);
+        tokio::spawn(reader.run(tokio::io::BufReader::new(output)));
+
+        writer
+            .write_all(
+                b"[12:00:01] [main/INFO] (ViaProxy) Binding proxy server to 127.0.0.1:25568...\n",
+            )
+            .await
+            .unwrap();
+        let started_at = std::time::Instant::now();
+        ready_rx.changed().await.unwrap();
+        assert!(started_at.elapsed() >= BIND_RESULT_GRACE / 2);
+        assert!(!bind_failed.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_parse_via_component() {
+        let line = "[12:00:02] [main/INFO] (ViaBackwards) ViaBackwards 5.2.1 is now loaded";
+        assert_eq!(
+            parse_via_component(line),
+            Some((ViaComponent::ViaBackwards, "5.2.1".to_owned()))
+        );
+        let line = "[12:00:02] [main/INFO] (ViaProxy) Loading ViaRewind v4.0.5-SNAPSHOT";
+        assert_eq!(
+            parse_via_component(line),
+            Some((ViaComponent::ViaRewind, "4.0.5-SNAPSHOT".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_parse_via_component_other_line() {
+        let line = "[12:00:02] [main/INFO] (ViaVersion) ViaVersion 5.2.1 is now loaded";
+        assert_eq!(parse_via_component(line), None);
+        let line = "[12:00:02] [main/INFO] (ViaBackwards) Loading translations...";
+        assert_eq!(parse_via_component(line), None);
+    }
+
+    #[test]
+    fn test_parse_fatal_error() {
+        let line = "[12:00:00] [main/FATAL] (ViaProxy) Invalid target address: localhost:9999999\n";
+        assert_eq!(
+            parse_fatal_error(line).as_deref(),
+            Some("Invalid target address: localhost:9999999")
+        );
+
+        let line = "[12:00:00] [main/ERROR] (ViaProxy) Failed to load plugin";
+        assert_eq!(parse_fatal_error(line), None);
+    }
+
+    #[test]
+    fn test_is_bind_failure() {
+        let line = "[12:00:03] [main/ERROR] (ViaProxy) Failed to bind proxy server: java.net.BindException: Address already in use";
+        assert!(is_bind_failure(line));
+        let line = "[12:00:03] [main/INFO] (ViaProxy) Binding proxy server to 127.0.0.1:25566";
+        assert!(!is_bind_failure(line));
+    }
+
+    #[tokio::test]
+    async fn test_forward_stderr() {
+        let stderr = "Error: Unable to access jarfile ViaProxy.jar\n";
+        let recent_output = Arc::new(Mutex::new(RecentOutput::new(50)));
+        forward_stderr(stderr.as_bytes(), recent_output.clone(), None).await;
+
+        let recent_output = recent_output.lock();
+        assert_eq!(
+            recent_output.lines,
+            ["[stderr] Error: Unable to access jarfile ViaProxy.jar"]
+        );
+    }
+
+    #[test]
+    fn test_recent_output_keeps_last_lines() {
+        let mut recent = RecentOutput::new(2);
+        for line in ["first\n", "second\n", "third\r\n"] {
+            recent.push(line);
+        }
+        assert_eq!(recent.lines, ["second", "third"]);
+
+        let error = recent.attach(
+            ViaStartError::NoOutput {
+                timeout: Duration::from_secs(1),
+            }
+            .into(),
+        );
+        assert_eq!(
+            error.to_string(),
+            "ViaProxy failed to start, its last 2 lines of output were:\nsecond\nthird"
+        );
+        assert!(error.downcast_ref::<ViaStartError>().is_some());
+    }
+
+    #[test]
+    fn test_recent_output_disabled() {
+        let mut recent = RecentOutput::new(0);
+        recent.push("line\n");
+        let error = recent.attach(anyhow::anyhow!("ViaProxy exited before it was ready"));
+        assert_eq!(error.to_string(), "ViaProxy exited before it was ready");
+    }
+
+    #[test]
+    fn test_parse_structured_record() {
+        let line = r#"{"time":"12:00:03","thread":"main","level":"ERROR","logger":"ViaProxy","message":"Failed to bind proxy server","thrown":"java.net.BindException: Address already in use\n\tat sun.nio.ch.Net.bind0(Native Method)"}"#;
+        let record = parse_structured_record(line).unwrap();
+        assert!(
+            record.starts_with("[12:00:03] [main/ERROR] (ViaProxy) Failed to bind proxy server")
+        );
+        assert!(is_bind_failure(&record));
+
+        let line = r#"{"time":"12:00:04","thread":"main","level":"WARN","logger":"ViaVersion","message":"Unknown \"packet\" id","thrown":""}"#;
+        let record = parse_structured_record(line).unwrap();
+        assert_eq!(
+            parse_translation_warning(&record).as_deref(),
+            Some("Unknown \"packet\" id")
+        );
+    }
+
+    #[test]
+    fn test_parse_structured_record_prose() {
+        let line = "[12:00:00] [main/INFO] (ViaProxy) Binding proxy server to 127.0.0.1:25566";
+        assert_eq!(parse_structured_record(line), None);
+        assert_eq!(parse_structured_record("{not json"), None);
+    }
+
+    #[test]
+    fn test_parse_via_proxy_version() {
+        let line = "[12:00:00] [main/INFO] (ViaProxy) Initializing ViaProxy CLI v3.3.7 (git-ViaProxy-3.3.7:0123abc)...";
+        let version = parse_via_proxy_version(line);
+        assert_eq!(
+            version.as_deref(),
+            Some("3.3.7 (git-ViaProxy-3.3.7:0123abc)")
+        );
+    }
+
+    #[test]
+    fn test_parse_via_proxy_version_other_line() {
+        let line =
+            "[12:00:01] [main/INFO] (ViaProxy) Using java version: OpenJDK 64-Bit Server VM 21.0.3";
+        assert_eq!(parse_via_proxy_version(line), None);
+    }
+
+    #[test]
+    fn test_parse_java_runtime() {
+        let line =
+            "[12:00:01] [main/INFO] (ViaProxy) Using java version: OpenJDK 64-Bit Server VM 21.0.3";
+        let (java_version, major) = parse_java_runtime(line).unwrap();
+        assert_eq!(java_version, "OpenJDK 64-Bit Server VM 21.0.3");
+        assert_eq!(major, 21);
+
+        let line = "Using java version: OpenJDK 64-Bit Server VM 1.8.0_392";
+        assert_eq!(parse_java_runtime(line).map(|(_, major)| major), Some(8));
+        let line = "Using java version: OpenJDK 64-Bit Server VM 24-ea";
+        assert_eq!(parse_java_runtime(line).map(|(_, major)| major), Some(24));
+    }
+
+    #[test]
+    fn test_parse_bound_address() {
+        let line = "[12:00:01] [main/INFO] (ViaProxy) Binding proxy server to /127.0.0.1:25568";
+        let address = parse_bound_address(line);
+        assert_eq!(address, Some(SocketAddr::from(([127, 0, 0, 1], 25568))));
+    }
+
+    #[test]
+    fn test_normalize_address() {
+        let ipv4 = SocketAddr::from(([127, 0, 0, 1], 25568));
+        let ipv6 = SocketAddr::from((Ipv6Addr::LOCALHOST, 25568));
+        assert_eq!(normalize_address("127.0.0.1:25568"), Some(ipv4));
+        assert_eq!(normalize_address("localhost/127.0.0.1:25568"), Some(ipv4));
+        assert_eq!(normalize_address("/0.0.0.0:25568"), Some(ipv4));
+        assert_eq!(normalize_address("[::]:25568"), Some(ipv6));
+        assert_eq!(normalize_address("/0:0:0:0:0:0:0:0:25568"), Some(ipv6));
+        assert_eq!(normalize_address("localhost:25568"), Some(ipv4));
+        assert_eq!(normalize_address("example.com:25568"), None);
+        assert_eq!(normalize_address("127.0.0.1"), None);
+    }
+
+    #[test]
+    fn test_parse_translation_warning() {
+        let line = "[12:00:02] [Netty Epoll Child #1/WARN] (ViaVersion) ERROR IN Protocol1_20_5To1_20_3 IN REMAP OF CHAT_COMMAND (PLAY)\n";
+        assert_eq!(
+            parse_translation_warning(line).as_deref(),
+            Some("ERROR IN Protocol1_20_5To1_20_3 IN REMAP OF CHAT_COMMAND (PLAY)")
+        );
+
+        let line = "[12:00:02] [Worker/WARN] Couldn't map packet 0x42";
+        assert_eq!(
+            parse_translation_warning(line).as_deref(),
+            Some("Couldn't map packet 0x42")
+        );
+    }
+
+    #[test]
+    fn test_parse_translation_warning_other_line() {
+        let line = "[12:00:02] [main/WARN] (ViaProxy) Failed to check for updates";
+        assert_eq!(parse_translation_warning(line), None);
+        let line = "[12:00:02] [Worker/INFO] (ViaVersion) Couldn't map packet 0x42";
+        assert_eq!(parse_translation_warning(line), None);
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let line = "\x1b[2K\x1b[1A[12:00:00] [main/\x1b[33mWARN\x1b[0m] (ViaVersion) Missing mapping\x1b[?25h";
+        assert_eq!(
+            strip_ansi(line),
+            "[12:00:00] [main/WARN] (ViaVersion) Missing mapping"
+        );
+        assert!(matches!(strip_ansi("plain"), Cow::Borrowed("plain")));
+    }
+
+    #[test]
+    fn test_log_file_rotates() {
+        let dir =
+            std::env::temp_dir().join(format!("azalea-viaversion-log-{}", std::process::id()));
+        let path = dir.join("viaproxy.log");
+        let mut log_file = LogFile::open(&path, Some(16)).unwrap();
+        log_file.write_line("first line\n");
+        log_file.write_line("second line\n");
+
+        let current = std::fs::read_to_string(&path);
+        let rotated = std::fs::read_to_string(dir.join("viaproxy.log.1"));
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(current.unwrap(), "second line\n");
+        assert_eq!(rotated.unwrap(), "first line\n");
+    }
+}
@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use reqwest::{Client, Url};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::download::{download_file, validate_file_name, DownloadOptions};
+
+/// Where a jar passed to
+/// [`ViaVersionBuilder::with_extra_plugin`](crate::ViaVersionBuilder::with_extra_plugin) comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PluginSource {
+    /// Downloaded like the other jars, and cached by its file name.
+    Url(Url),
+    /// Copied from disk every time ViaProxy is started, so changes are picked up.
+    Path(PathBuf),
+}
+
+impl PluginSource {
+    /// Treat `source` as a URL if it's an HTTP or HTTPS one, and as a path otherwise.
+    pub(crate) fn parse(source: &str) -> Self {
+        match Url::parse(source) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Self::Url(url),
+            _ => Self::Path(PathBuf::from(source)),
+        }
+    }
+
+    /// The name the jar is saved as in ViaProxy's `plugins` folder.
+    ///
+    /// # Errors
+    /// Will return `Err` if the source has no file name, or it could escape the `plugins` folder.
+    pub(crate) fn file_name(&self) -> Result<String> {
+        let file = match self {
+            Self::Url(url) => url
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .map(str::to_owned),
+            Self::Path(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        };
+
+        let file = file
+            .filter(|file| !file.is_empty())
+            .with_context(|| format!("Extra plugin {self} has no file name"))?;
+        validate_file_name(&file)?;
+
+        Ok(file)
+    }
+
+    /// Download or copy the jar into `plugins_dir`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the jar fails to download or copy.
+    pub(crate) async fn install(
+        &self,
+        client: &Client,
+        plugins_dir: &Path,
+        options: &DownloadOptions,
+    ) -> Result<()> {
+        let file = self.file_name()?;
+        match self {
+            Self::Url(url) => {
+                download_file(client, url.clone(), plugins_dir, &file, options).await?;
+            }
+            Self::Path(path) => {
+                tokio::fs::create_dir_all(plugins_dir).await?;
+                tokio::fs::copy(path, plugins_dir.join(&file)).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for PluginSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Url(url) => write!(f, "{url}"),
+            Self::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plugin_source_file_name() {
+        let source = PluginSource::parse("https://example.com/releases/Addon-1.0.jar?raw=1");
+        assert!(matches!(source, PluginSource::Url(_)));
+        assert_eq!(source.file_name().unwrap(), "Addon-1.0.jar");
+
+        let source = PluginSource::parse("addons/Logger.jar");
+        assert_eq!(
+            source,
+            PluginSource::Path(PathBuf::from("addons/Logger.jar"))
+        );
+        assert_eq!(source.file_name().unwrap(), "Logger.jar");
+    }
+
+    #[test]
+    fn test_plugin_source_without_file_name() {
+        for source in ["https://example.com/", "addons/.."] {
+            assert!(PluginSource::parse(source).file_name().is_err());
+        }
+    }
+}
@@ -0,0 +1,807 @@
+use anyhow::{anyhow, bail, Context, Result};
+use parking_lot::RwLock;
+use std::{
+    ffi::OsString,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncWriteExt, BufReader},
+    net::TcpListener,
+    process::{Child, ChildStdin, Command},
+    runtime::Handle,
+    sync::{mpsc, Mutex},
+};
+use tracing::{debug, warn};
+
+use crate::{
+    auth::PendingAuthTasks,
+    builder::{PreSpawnHook, ReadyMilestone, WildcardDomainHandling},
+    metrics,
+    output::{forward_stderr, LogFile, OutputReader, RecentOutput},
+    proxy::BackendProxy,
+    reserve_free_addr, spawn_on,
+    versions::target_version,
+    warm, ReservedAddr, ViaStartError, ViaStartupReport,
+};
+
+/// How long [`ViaController::stop`] waits for ViaProxy to exit before killing it.
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How many translation warnings are kept until the plugin reads them, newer ones are dropped.
+const WARNING_BUFFER: usize = 256;
+
+/// How often [`ViaVersionBuilder::with_auto_restart`](crate::ViaVersionBuilder::with_auto_restart)
+/// checks whether ViaProxy is still running.
+const AUTO_RESTART_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the first automatic restart waits, doubled for each one that follows closely.
+const AUTO_RESTART_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest wait between automatic restarts, and how long ViaProxy has to stay up for the
+/// wait to go back down.
+const AUTO_RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Everything needed to spawn ViaProxy again after it was stopped.
+pub(crate) struct SpawnConfig {
+    pub(crate) java: PathBuf,
+    pub(crate) jvm_args: Vec<String>,
+    pub(crate) via_proxy_jar: PathBuf,
+    pub(crate) config_file: Option<PathBuf>,
+    pub(crate) log_config: Option<PathBuf>,
+    pub(crate) java_major: u64,
+    pub(crate) connect_host: IpAddr,
+    pub(crate) working_dir: PathBuf,
+    pub(crate) auth_method: &'static str,
+    pub(crate) proxy_online_mode: bool,
+    pub(crate) persistent: bool,
+    pub(crate) via_debug: bool,
+    pub(crate) ready_milestone: ReadyMilestone,
+    pub(crate) wildcard_domain_handling: WildcardDomainHandling,
+    pub(crate) compression_threshold: Option<i32>,
+    pub(crate) legacy_skin_loading: Option<bool>,
+    /// Passed to ViaProxy after every argument the plugin manages.
+    pub(crate) extra_args: Vec<String>,
+    pub(crate) stdout_buffer_size: usize,
+    pub(crate) no_output_timeout: Duration,
+    pub(crate) startup_timeout: Duration,
+    pub(crate) output_context_lines: usize,
+    pub(crate) log_file: Option<PathBuf>,
+    pub(crate) log_file_max_size: Option<u64>,
+    pub(crate) bind_retries: u32,
+    /// The port set with [`ViaVersionBuilder::with_bind_addr`](crate::ViaVersionBuilder::with_bind_addr),
+    /// which isn't moved to another one when it's taken.
+    pub(crate) bind_port: Option<u16>,
+    pub(crate) auto_restart: bool,
+    pub(crate) envs: Vec<(OsString, OsString)>,
+    pub(crate) clear_env: bool,
+    pub(crate) runtime: Option<Handle>,
+    pub(crate) pre_spawn: Option<PreSpawnHook>,
+}
+
+impl SpawnConfig {
+    fn command(
+        &self,
+        bind_addr: SocketAddr,
+        mc_version: &str,
+        backend_proxy: Option<&BackendProxy>,
+    ) -> Command {
+        let mut command = Command::new(&self.java);
+
+        /* Java Args */
+        if let Some(log_config) = &self.log_config {
+            let mut property = OsString::from("-Dlog4j2.configurationFile=");
+            property.push(log_config);
+            command.arg(property);
+        }
+        /* After the plugin's own properties, so they can be overridden */
+        command.args(&self.jvm_args);
+
+        command
+            .arg("-jar")
+            .arg(&self.via_proxy_jar)
+            .current_dir(&self.working_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(!self.persistent);
+
+        /* ViaProxy Args, which override the config file's settings */
+        match &self.config_file {
+            Some(config_file) => command.arg("config").arg(config_file),
+            None => command.arg("cli"),
+        };
+
+        command
+            .args(["--auth-method", self.auth_method])
+            .args(["--bind-address", &bind_addr.to_string()])
+            .args(["--target-address", "127.0.0.1:0"])
+            .args(["--target-version", mc_version])
+            .args([
+                "--wildcard-domain-handling",
+                self.wildcard_domain_handling.as_arg(),
+            ]);
+
+        #[cfg(unix)]
+        if self.persistent {
+            /* Don't receive the app's Ctrl-C */
+            command.process_group(0);
+        }
+
+        if self.proxy_online_mode {
+            command.args(["--proxy-online-mode", "true"]);
+        }
+
+        if let Some(threshold) = self.compression_threshold {
+            command.args(["--compression-threshold", &threshold.to_string()]);
+        }
+
+        if let Some(legacy_skin_loading) = self.legacy_skin_loading {
+            command.args(["--legacy-skin-loading", &legacy_skin_loading.to_string()]);
+        }
+
+        if let Some(proxy) = backend_proxy {
+            command.args(["--backend-proxy-url", &proxy.url()]);
+        }
+
+        command.args(&self.extra_args);
+
+        if self.clear_env {
+            command.env_clear();
+        }
+        command.envs(self.envs.iter().map(|(key, value)| (key, value)));
+
+        if let Some(pre_spawn) = &self.pre_spawn {
+            pre_spawn(&mut command);
+        }
+
+        command
+    }
+}
+
+/// The ViaProxy process, shared between the plugin and its controller.
+pub(crate) struct ViaProxyProcess {
+    /// How to spawn ViaProxy, or `None` if it runs outside the plugin.
+    config: Option<SpawnConfig>,
+    connect_host: IpAddr,
+    bind_addr: RwLock<SocketAddr>,
+    /// Holds the port until ViaProxy is spawned, and again after a bind retry.
+    reserved: parking_lot::Mutex<Option<TcpListener>>,
+    mc_version: RwLock<String>,
+    backend_proxy: RwLock<Option<BackendProxy>>,
+    child: Mutex<Option<Child>>,
+    stdin: Mutex<Option<ChildStdin>>,
+    /// Whether the plugin is stopping the current child, replaced for every spawn.
+    exit_expected: parking_lot::Mutex<Arc<AtomicBool>>,
+    report: Arc<RwLock<ViaStartupReport>>,
+    warnings_tx: mpsc::Sender<String>,
+    warnings_rx: parking_lot::Mutex<mpsc::Receiver<String>>,
+    /// Whether the auto restart task has been started, so a warm proxy doesn't get two.
+    supervised: AtomicBool,
+    /// How ViaProxy exited before each automatic restart the plugin hasn't sent an event for yet.
+    restarts: parking_lot::Mutex<Vec<ExitStatus>>,
+}
+
+impl ViaProxyProcess {
+    pub(crate) fn new(
+        config: SpawnConfig,
+        reserved: ReservedAddr,
+        mc_version: String,
+        backend_proxy: Option<BackendProxy>,
+    ) -> Self {
+        let (warnings_tx, warnings_rx) = mpsc::channel(WARNING_BUFFER);
+        Self {
+            connect_host: config.connect_host,
+            config: Some(config),
+            bind_addr: RwLock::new(reserved.addr),
+            reserved: parking_lot::Mutex::new(Some(reserved.listener)),
+            mc_version: RwLock::new(target_version(&mc_version)),
+            backend_proxy: RwLock::new(backend_proxy),
+            child: Mutex::new(None),
+            stdin: Mutex::new(None),
+            exit_expected: parking_lot::Mutex::default(),
+            report: Arc::default(),
+            warnings_tx,
+            warnings_rx: parking_lot::Mutex::new(warnings_rx),
+            supervised: AtomicBool::new(false),
+            restarts: parking_lot::Mutex::default(),
+        }
+    }
+
+    /// A ViaProxy the plugin didn't spawn and can't restart, which bots reach at `connect_host`.
+    pub(crate) fn external(
+        bind_addr: SocketAddr,
+        connect_host: IpAddr,
+        mc_version: String,
+    ) -> Self {
+        let (warnings_tx, warnings_rx) = mpsc::channel(WARNING_BUFFER);
+        Self {
+            config: None,
+            connect_host,
+            bind_addr: RwLock::new(bind_addr),
+            reserved: parking_lot::Mutex::new(None),
+            mc_version: RwLock::new(target_version(&mc_version)),
+            backend_proxy: RwLock::new(None),
+            child: Mutex::new(None),
+            stdin: Mutex::new(None),
+            exit_expected: parking_lot::Mutex::default(),
+            report: Arc::default(),
+            warnings_tx,
+            warnings_rx: parking_lot::Mutex::new(warnings_rx),
+            supervised: AtomicBool::new(false),
+            restarts: parking_lot::Mutex::default(),
+        }
+    }
+
+    pub(crate) fn bind_addr(&self) -> SocketAddr {
+        *self.bind_addr.read()
+    }
+
+    /// Whether ViaProxy is still running.
+    pub(crate) async fn is_running(&self) -> bool {
+        let mut child = self.child.lock().await;
+        child
+            .as_mut()
+            .is_some_and(|child| matches!(child.try_wait(), Ok(None)))
+    }
+
+    /// The address bots connect to, which differs from the bind address for wildcard binds.
+    pub(crate) fn connect_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.connect_host, self.bind_addr().port())
+    }
+
+    pub(crate) fn mc_version(&self) -> String {
+        self.mc_version.read().clone()
+    }
+
+    pub(crate) fn set_mc_version(&self, mc_version: String) {
+        *self.mc_version.write() = target_version(&mc_version);
+    }
+
+    pub(crate) fn backend_proxy(&self) -> Option<BackendProxy> {
+        self.backend_proxy.read().clone()
+    }
+
+    pub(crate) fn set_backend_proxy(&self, backend_proxy: Option<BackendProxy>) {
+        *self.backend_proxy.write() = backend_proxy;
+    }
+
+    pub(crate) fn report(&self) -> ViaStartupReport {
+        self.report.read().clone()
+    }
+
+    pub(crate) fn update_report(&self, update: impl FnOnce(&mut ViaStartupReport)) {
+        update(&mut self.report.write());
+    }
+
+    /// Take the translation warnings logged since this was last called.
+    pub(crate) fn drain_translation_warnings(&self) -> Vec<String> {
+        let mut warnings_rx = self.warnings_rx.lock();
+        std::iter::from_fn(|| warnings_rx.try_recv().ok()).collect()
+    }
+
+    /// Take how ViaProxy exited before each automatic restart since this was last called.
+    pub(crate) fn drain_restarts(&self) -> Vec<ExitStatus> {
+        std::mem::take(&mut *self.restarts.lock())
+    }
+
+    /// How ViaProxy exited, if it did without the plugin stopping it.
+    async fn unexpected_exit(&self) -> Option<ExitStatus> {
+        let mut child = self.child.lock().await;
+        let status = child.as_mut()?.try_wait().ok()??;
+        (!self.exit_expected.lock().load(Ordering::Relaxed)).then_some(status)
+    }
+
+    /// Start restarting ViaProxy whenever it exits on its own, if the builder asked for it.
+    ///
+    /// The task only holds a weak reference, so it stops once the plugin and its controllers
+    /// have been dropped.
+    pub(crate) fn supervise(self: &Arc<Self>) {
+        let Some(config) = self.config.as_ref().filter(|config| config.auto_restart) else {
+            return;
+        };
+        if self.supervised.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let weak = Arc::downgrade(self);
+        spawn_on(config.runtime.as_ref(), async move {
+            let mut delay = AUTO_RESTART_MIN_DELAY;
+            let mut restarted_at: Option<Instant> = None;
+            loop {
+                tokio::time::sleep(AUTO_RESTART_INTERVAL).await;
+                let Some(process) = weak.upgrade() else {
+                    break;
+                };
+                let Some(status) = process.unexpected_exit().await else {
+                    continue;
+                };
+
+                /* Only back off from a ViaProxy that keeps crashing soon after starting */
+                if restarted_at.is_some_and(|at| at.elapsed() > AUTO_RESTART_MAX_DELAY) {
+                    delay = AUTO_RESTART_MIN_DELAY;
+                }
+                warn!("ViaProxy exited ({status}), restarting it in {delay:?}");
+                drop(process);
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(AUTO_RESTART_MAX_DELAY);
+                restarted_at = Some(Instant::now());
+
+                /* It may have been stopped or restarted through a controller while we waited */
+                let Some(process) = weak.upgrade() else {
+                    break;
+                };
+                if process.unexpected_exit().await.is_none() {
+                    continue;
+                }
+
+                /* The exited child is only replaced once the new one is ready, so a failed
+                 * restart is noticed and tried again on the next check */
+                metrics::record_restart();
+                match process.spawn().await {
+                    Ok(()) => process.restarts.lock().push(status),
+                    Err(error) => warn!("Failed to restart ViaProxy: {error:#}"),
+                }
+            }
+        });
+    }
+
+    /// Spawn ViaProxy and wait until it's ready, moving to another free port if its port was
+    /// taken in the meantime.
+    ///
+    /// Bots pick up the new port on the next update, since the plugin reads it every time.
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy runs outside the plugin, since it can't be spawned again.
+    pub(crate) async fn spawn(&self) -> Result<()> {
+        let Some(config) = &self.config else {
+            bail!("ViaProxy was started outside the plugin, so it can't be spawned");
+        };
+
+        let mut retries = 0;
+        loop {
+            let error = match self.spawn_once(config).await {
+                Err(error) if config.bind_port.is_none() && retries < config.bind_retries => error,
+                result => return result,
+            };
+            let Some(&ViaStartError::PortInUse { addr }) = error.downcast_ref::<ViaStartError>()
+            else {
+                return Err(error);
+            };
+
+            retries += 1;
+            let reserved = reserve_free_addr(addr.ip()).context("Failed to bind")?;
+            warn!(
+                "ViaProxy failed to bind {addr}, retrying on {}",
+                reserved.addr
+            );
+            *self.bind_addr.write() = reserved.addr;
+            *self.reserved.lock() = Some(reserved.listener);
+        }
+    }
+
+    async fn spawn_once(&self, config: &SpawnConfig) -> Result<()> {
+        let backend_proxy = self.backend_proxy();
+        let mut command =
+            config.command(self.bind_addr(), &self.mc_version(), backend_proxy.as_ref());
+        if let Some(proxy) = &backend_proxy {
+            debug!("Connecting to servers through {}", proxy.redacted_url());
+        }
+
+        let log_file = match &config.log_file {
+            Some(path) => Some(Arc::new(parking_lot::Mutex::new(LogFile::open(
+                path,
+                config.log_file_max_size,
+            )?))),
+            None => None,
+        };
+
+        /* Release the port at the last moment, so ViaProxy can bind it */
+        drop(self.reserved.lock().take());
+        let spawned_at = Instant::now();
+        let mut child = command.spawn().context("Failed to spawn ViaProxy")?;
+        let stdout = child.stdout.take().context("Failed to get stdout")?;
+        let stderr = child.stderr.take().context("Failed to get stderr")?;
+        let stdin = child.stdin.take();
+
+        let (ready_tx, mut rx) = tokio::sync::watch::channel(());
+        let (first_output_tx, first_output_rx) = tokio::sync::oneshot::channel();
+        let bind_failed = Arc::new(AtomicBool::new(false));
+        let fatal_error = Arc::new(parking_lot::Mutex::new(None));
+        let exit_expected = Arc::new(AtomicBool::new(false));
+        *self.exit_expected.lock() = exit_expected.clone();
+        let recent_output = Arc::new(parking_lot::Mutex::new(RecentOutput::new(
+            config.output_context_lines,
+        )));
+        let reader = OutputReader {
+            report: self.report.clone(),
+            via_debug: config.via_debug,
+            warnings_tx: self.warnings_tx.clone(),
+            ready_marker: config.ready_milestone.marker(),
+            ready_before_bind: config.ready_milestone.precedes_bind(),
+            ready_tx,
+            first_output_tx: Some(first_output_tx),
+            java_major: config.java_major,
+            bind_failed: bind_failed.clone(),
+            exit_expected,
+            fatal_error: fatal_error.clone(),
+            recent_output: recent_output.clone(),
+            log_file: log_file.clone(),
+        };
+        let stdout = BufReader::with_capacity(config.stdout_buffer_size, stdout);
+        spawn_on(config.runtime.as_ref(), reader.run(stdout));
+        spawn_on(
+            config.runtime.as_ref(),
+            forward_stderr(BufReader::new(stderr), recent_output.clone(), log_file),
+        );
+
+        /* A JVM that never prints anything is stuck, not just slow */
+        let no_output_timeout = config.no_output_timeout;
+        if tokio::time::timeout(no_output_timeout, first_output_rx)
+            .await
+            .is_err()
+        {
+            let _ = child.start_kill();
+            let error = ViaStartError::NoOutput {
+                timeout: no_output_timeout,
+            };
+            return Err(recent_output.lock().attach(error.into()));
+        }
+        let first_output_at = Instant::now();
+
+        /* Wait until ViaProxy is ready, the timeout counts from the spawn */
+        let startup_timeout = config.startup_timeout;
+        let ready = match spawned_at.checked_add(startup_timeout) {
+            Some(deadline) => tokio::time::timeout_at(deadline.into(), rx.changed()).await,
+            None => Ok(rx.changed().await), /* Too far away to ever time out */
+        };
+        let Ok(ready) = ready else {
+            /* ViaProxy is still running, so wait for it to exit to free its port */
+            let _ = child.kill().await;
+            let error = ViaStartError::StartupTimeout {
+                timeout: startup_timeout,
+            };
+            return Err(recent_output.lock().attach(error.into()));
+        };
+
+        if ready.is_err() {
+            let _ = child.start_kill();
+            let fatal_error = fatal_error.lock().take();
+            let error = if bind_failed.load(Ordering::Relaxed) {
+                ViaStartError::PortInUse {
+                    addr: self.bind_addr(),
+                }
+                .into()
+            } else if let Some(message) = fatal_error {
+                ViaStartError::Fatal { message }.into()
+            } else {
+                anyhow!("ViaProxy exited before it was ready")
+            };
+
+            /* The stdout reader has stopped, so it has stored every line by now */
+            return Err(recent_output.lock().attach(error));
+        }
+
+        let spawn = first_output_at - spawned_at;
+        let ready = first_output_at.elapsed();
+        debug!("ViaProxy printed its first line after {spawn:?} and was ready {ready:?} later");
+        self.update_report(|report| {
+            report.timings.spawn = spawn;
+            report.timings.ready = ready;
+        });
+
+        *self.child.lock().await = Some(child);
+        *self.stdin.lock().await = stdin;
+
+        if config.via_debug {
+            /* ViaProxy forwards this to ViaVersion, which toggles its debug logging */
+            self.send_command("viaversion debug").await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a line to ViaProxy's console.
+    pub(crate) async fn send_command(&self, command: &str) -> Result<()> {
+        let mut stdin = self.stdin.lock().await;
+        let stdin = stdin.as_mut().context("ViaProxy isn't running")?;
+        stdin.write_all(format!("{command}\n").as_bytes()).await?;
+        stdin.flush().await?;
+
+        Ok(())
+    }
+
+    /// Start killing ViaProxy without waiting for it to exit, unless it's persistent or kept warm.
+    ///
+    /// This is for when the app is exiting, so it gives up if a restart holds the child.
+    pub(crate) fn start_kill(&self) {
+        /* A ViaProxy started outside the plugin is left running too */
+        if self
+            .config
+            .as_ref()
+            .map_or(true, |config| config.persistent)
+        {
+            return;
+        }
+
+        /* The warm proxy is meant to outlive the app, for the next one in this process */
+        if warm::is_warm(self) {
+            return;
+        }
+
+        self.expect_exit();
+        if let Ok(mut child) = self.child.try_lock() {
+            if let Some(child) = child.as_mut() {
+                let _ = child.start_kill();
+            }
+        }
+    }
+
+    /// Mark the running ViaProxy as being stopped by the plugin, so it exiting isn't warned about.
+    fn expect_exit(&self) {
+        self.exit_expected.lock().store(true, Ordering::Relaxed);
+    }
+
+    /// Kill ViaProxy and wait for it to exit.
+    pub(crate) async fn shutdown(&self) -> Result<()> {
+        self.expect_exit();
+        self.stdin.lock().await.take();
+        if let Some(mut child) = self.child.lock().await.take() {
+            /* A crashed ViaProxy may already be reaped, and killing it again would fail */
+            if matches!(child.try_wait(), Ok(None)) {
+                child.kill().await.context("Failed to kill ViaProxy")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ask ViaProxy to stop and wait for it to exit, killing it if it takes too long.
+    ///
+    /// Returns `None` if ViaProxy was already stopped.
+    pub(crate) async fn stop(&self, timeout: Duration) -> Result<Option<ExitStatus>> {
+        self.expect_exit();
+        let stdin = self.stdin.lock().await.take();
+        let Some(mut child) = self.child.lock().await.take() else {
+            return Ok(None);
+        };
+
+        if let Some(mut stdin) = stdin {
+            /* The console's stop command, this fails if ViaProxy already exited */
+            let _ = stdin.write_all(b"stop\n").await;
+            let _ = stdin.flush().await;
+        }
+
+        if let Ok(status) = tokio::time::timeout(timeout, child.wait()).await {
+            return status.map(Some).context("Failed to wait for ViaProxy");
+        }
+
+        warn!("ViaProxy didn't stop within {timeout:?}, killing it");
+        child.kill().await.context("Failed to kill ViaProxy")?;
+        child
+            .wait()
+            .await
+            .map(Some)
+            .context("Failed to wait for ViaProxy")
+    }
+}
+
+/// Controls the ViaProxy process from outside the ECS.
+///
+/// Returned alongside the plugin by [`ViaVersionBuilder::start`](crate::ViaVersionBuilder::start).
+/// ViaProxy keeps running until the plugin and every controller have been dropped.
+#[derive(Clone)]
+pub struct ViaController {
+    process: Arc<ViaProxyProcess>,
+    pending_auth_tasks: Arc<PendingAuthTasks>,
+}
+
+impl fmt::Debug for ViaController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ViaController")
+            .field("bind_addr", &self.process.bind_addr())
+            .field("mc_version", &self.process.mc_version())
+            .field("pending_auth_tasks", &self.pending_auth_tasks.count())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ViaController {
+    pub(crate) const fn new(
+        process: Arc<ViaProxyProcess>,
+        pending_auth_tasks: Arc<PendingAuthTasks>,
+    ) -> Self {
+        Self {
+            process,
+            pending_auth_tasks,
+        }
+    }
+
+    /// The address ViaProxy is listening on.
+    #[must_use]
+    pub fn bind_addr(&self) -> SocketAddr {
+        self.process.bind_addr()
+    }
+
+    /// The address bots connect to ViaProxy at.
+    #[must_use]
+    pub fn connect_addr(&self) -> SocketAddr {
+        self.process.connect_addr()
+    }
+
+    /// How many OpenAuthMod joins are still waiting on Mojang, across every bot.
+    #[must_use]
+    pub fn pending_auth_tasks(&self) -> usize {
+        self.pending_auth_tasks.count()
+    }
+
+    /// Wait until every pending OpenAuthMod join has finished, like before [`Self::stop`] so no
+    /// request to Mojang is abandoned halfway.
+    ///
+    /// Joins that start while waiting are waited for too. Wrap this in a timeout if Mojang may be
+    /// slow, since each join can take as long as its retries and timeouts allow.
+    pub async fn wait_for_auth_tasks(&self) {
+        self.pending_auth_tasks.drained().await;
+    }
+
+    /// Send a command to ViaProxy's console.
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy isn't running or the command fails to write.
+    pub async fn send_command(&self, command: &str) -> Result<()> {
+        self.process.send_command(command).await
+    }
+
+    /// Stop ViaProxy, disconnecting every bot using it.
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy fails to be killed.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.process.shutdown().await
+    }
+
+    /// Gracefully stop ViaProxy and wait until it has exited and released its port.
+    ///
+    /// ViaProxy is killed if it doesn't exit within 10 seconds. Returns its exit status, or
+    /// `None` if it was already stopped, so this is safe to call more than once.
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy fails to be killed or waited on.
+    pub async fn stop(self) -> Result<Option<ExitStatus>> {
+        self.process.stop(STOP_TIMEOUT).await
+    }
+
+    /// Stop ViaProxy and start it again with the same settings, like after it crashed.
+    ///
+    /// This waits until ViaProxy is ready again, like [`ViaVersionBuilder::start`](crate::ViaVersionBuilder::start).
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy fails to stop or start again.
+    pub async fn restart(&self) -> Result<()> {
+        self.process.shutdown().await?;
+        metrics::record_restart();
+        self.process.spawn().await
+    }
+
+    /// Stop ViaProxy and start it again translating to another Minecraft version.
+    ///
+    /// Bots that join afterwards are routed to the new version. Connected bots are disconnected,
+    /// and since the address is rewritten every update, reconnecting picks up the new version.
+    ///
+    /// # Errors
+    /// Will return `Err` if ViaProxy fails to stop or start again.
+    pub async fn restart_with_version(&self, mc_version: impl ToString) -> Result<()> {
+        self.process.shutdown().await?;
+        self.process.set_mc_version(mc_version.to_string());
+        warm::update(&self.process);
+        metrics::record_restart();
+        self.process.spawn().await
+    }
+
+    /// Stop ViaProxy and start it again connecting to servers through another proxy, or directly
+    /// with `None`.
+    ///
+    /// ViaProxy can only change its proxy on startup, so every connected bot is disconnected.
+    /// Bots connect through the new proxy the next time they join.
+    ///
+    /// # Errors
+    /// Will return `Err` if an HTTP proxy's URL isn't `http://`, leaving ViaProxy running, or if
+    /// ViaProxy fails to stop or start again.
+    pub async fn set_backend_proxy(&self, proxy: Option<BackendProxy>) -> Result<()> {
+        if let Some(proxy) = &proxy {
+            proxy.validate()?;
+        }
+        self.process.shutdown().await?;
+        self.process.set_backend_proxy(proxy);
+        warm::update(&self.process);
+        metrics::record_restart();
+        self.process.spawn().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settings for a ViaProxy that's never spawned.
+    fn test_config() -> SpawnConfig {
+        SpawnConfig {
+            java: PathBuf::from("java"),
+            jvm_args: Vec::new(),
+            via_proxy_jar: PathBuf::from("ViaProxy.jar"),
+            config_file: None,
+            log_config: None,
+            java_major: 21,
+            connect_host: IpAddr::from([127, 0, 0, 1]),
+            working_dir: PathBuf::from("."),
+            auth_method: "NONE",
+            proxy_online_mode: false,
+            persistent: false,
+            via_debug: false,
+            ready_milestone: ReadyMilestone::MappingsLoaded,
+            wildcard_domain_handling: WildcardDomainHandling::Internal,
+            compression_threshold: Some(256),
+            legacy_skin_loading: None,
+            extra_args: vec!["--chat-signing".to_owned(), "false".to_owned()],
+            stdout_buffer_size: 8 * 1024,
+            no_output_timeout: Duration::from_secs(30),
+            startup_timeout: Duration::from_secs(120),
+            output_context_lines: 50,
+            log_file: None,
+            log_file_max_size: None,
+            bind_retries: 2,
+            bind_port: None,
+            auto_restart: false,
+            envs: Vec::new(),
+            clear_env: false,
+            runtime: None,
+            pre_spawn: None,
+        }
+    }
+
+    #[test]
+    fn test_command_extra_args_come_last() {
+        let config = test_config();
+        let command = config.command(SocketAddr::from(([127, 0, 0, 1], 25565)), "1.8", None);
+        let args: Vec<_> = command.as_std().get_args().collect();
+        assert_eq!(
+            args[args.len() - 4..],
+            ["--compression-threshold", "256", "--chat-signing", "false"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_start_kill_keeps_warm_proxy() {
+        let localhost = IpAddr::from([127, 0, 0, 1]);
+        let reserved = reserve_free_addr(localhost).unwrap();
+        let process = Arc::new(ViaProxyProcess::new(
+            test_config(),
+            reserved,
+            "1.8".to_owned(),
+            None,
+        ));
+        /* Stands in for ViaProxy, which isn't downloaded in tests */
+        let child = Command::new("sleep").arg("30").kill_on_drop(true).spawn();
+        *process.child.lock().await = Some(child.unwrap());
+        let key = warm::WarmKey::new(&test_config(), "1.8", localhost, None, &[]);
+        warm::keep(key, process.clone());
+
+        process.start_kill();
+        let exit_expected = process.exit_expected.lock().load(Ordering::Relaxed);
+        let running = process.is_running().await;
+        if let Some(child) = process.child.lock().await.as_mut() {
+            let _ = child.start_kill();
+        }
+
+        assert!(!exit_expected);
+        assert!(running);
+    }
+}
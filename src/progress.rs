@@ -0,0 +1,225 @@
+use anyhow::Result;
+#[cfg(feature = "progress")]
+use kdam::{tqdm, Bar, BarExt};
+#[cfg(feature = "progress")]
+use std::io::IsTerminal;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::info;
+
+pub(crate) type ProgressHook = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+/// How much each chunk moves the download speed estimate.
+const RATE_SMOOTHING: f64 = 0.2;
+
+/// A snapshot of a download, passed to
+/// [`ViaVersionBuilder::on_download_progress`](crate::ViaVersionBuilder::on_download_progress).
+#[derive(Clone, Debug)]
+pub struct DownloadProgress {
+    /// The name of the file being downloaded.
+    pub file: String,
+    /// How many bytes have been received so far.
+    pub received: usize,
+    /// The size of the file, if the server sent it.
+    pub total: Option<usize>,
+    /// The recent download speed, smoothed over the last few chunks.
+    pub bytes_per_sec: f64,
+    /// The estimated time left, if the size and speed are known.
+    pub eta: Option<Duration>,
+}
+
+/// An exponential moving average of the download speed.
+#[derive(Default)]
+struct RateEstimate {
+    bytes_per_sec: Option<f64>,
+}
+
+impl RateEstimate {
+    #[allow(clippy::cast_precision_loss)]
+    fn update(&mut self, len: usize, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return; /* Chunks that arrive together are counted with the next one */
+        }
+
+        let rate = len as f64 / seconds;
+        self.bytes_per_sec = Some(match self.bytes_per_sec {
+            Some(previous) => previous + RATE_SMOOTHING * (rate - previous),
+            None => rate,
+        });
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn eta(&self, remaining: usize) -> Option<Duration> {
+        let bytes_per_sec = self.bytes_per_sec.filter(|&rate| rate > 0.0)?;
+        Some(Duration::from_secs_f64(remaining as f64 / bytes_per_sec))
+    }
+}
+
+/// Reports the progress of a download.
+///
+/// With the `progress` feature this renders a progress bar when stderr is a terminal, otherwise it
+/// logs every 10%, so logs collected by systemd or Docker stay free of control characters.
+pub(crate) struct Progress {
+    #[cfg(feature = "progress")]
+    bar: Option<Bar>,
+    logged_percent: usize,
+    tracker: Tracker,
+}
+
+impl Progress {
+    /// `show_bar` forces the progress bar on or off, instead of checking for a terminal.
+    #[cfg_attr(not(feature = "progress"), allow(clippy::unnecessary_wraps))]
+    pub(crate) fn new(
+        file: &str,
+        total: Option<usize>,
+        position: u16,
+        hook: Option<ProgressHook>,
+        show_bar: Option<bool>,
+    ) -> Result<Self> {
+        let tracker = Tracker::new(file, total, hook);
+
+        /* kdam draws on stderr, so that's the stream that has to be a terminal */
+        #[cfg(feature = "progress")]
+        if show_bar.unwrap_or_else(|| std::io::stderr().is_terminal()) {
+            let mut bar = tqdm!(
+                total = total.unwrap_or(0),
+                position = position,
+                unit_scale = true,
+                unit_divisor = 1024,
+                unit = "B",
+                force_refresh = true
+            );
+
+            bar.write(format!("Downloading {file}"))?;
+
+            return Ok(Self {
+                bar: Some(bar),
+                logged_percent: 0,
+                tracker,
+            });
+        }
+        #[cfg(not(feature = "progress"))]
+        let _ = (position, show_bar);
+
+        info!("Downloading {file}");
+
+        Ok(Self {
+            #[cfg(feature = "progress")]
+            bar: None,
+            logged_percent: 0,
+            tracker,
+        })
+    }
+
+    #[cfg_attr(not(feature = "progress"), allow(clippy::unnecessary_wraps))]
+    pub(crate) fn update(&mut self, len: usize) -> Result<()> {
+        self.tracker.update(len);
+
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &mut self.bar {
+            bar.update(len)?;
+            return Ok(());
+        }
+
+        if let Some(total) = self.tracker.total.filter(|&total| total > 0) {
+            let percent = self.tracker.received * 100 / total;
+            if percent >= self.logged_percent + 10 {
+                self.logged_percent = percent - percent % 10;
+                info!("Downloading {}: {percent}%", self.tracker.file);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg_attr(not(feature = "progress"), allow(clippy::unnecessary_wraps))]
+    pub(crate) fn finish(&mut self) -> Result<()> {
+        #[cfg(feature = "progress")]
+        if let Some(bar) = &mut self.bar {
+            bar.refresh()?;
+            return Ok(());
+        }
+
+        info!("Downloaded {}", self.tracker.file);
+        Ok(())
+    }
+}
+
+/// Counts the bytes of a download and reports its speed to the hook.
+struct Tracker {
+    file: String,
+    total: Option<usize>,
+    received: usize,
+    pending: usize,
+    rate: RateEstimate,
+    last_sample: Instant,
+    hook: Option<ProgressHook>,
+}
+
+impl Tracker {
+    fn new(file: &str, total: Option<usize>, hook: Option<ProgressHook>) -> Self {
+        Self {
+            file: file.to_owned(),
+            total,
+            received: 0,
+            pending: 0,
+            rate: RateEstimate::default(),
+            last_sample: Instant::now(),
+            hook,
+        }
+    }
+
+    fn update(&mut self, len: usize) {
+        self.received += len;
+        self.pending += len;
+
+        let now = Instant::now();
+        let elapsed = now - self.last_sample;
+        if elapsed.is_zero() {
+            return;
+        }
+
+        self.rate.update(self.pending, elapsed);
+        self.pending = 0;
+        self.last_sample = now;
+
+        if let Some(hook) = &self.hook {
+            hook(&DownloadProgress {
+                file: self.file.clone(),
+                received: self.received,
+                total: self.total,
+                bytes_per_sec: self.rate.bytes_per_sec.unwrap_or_default(),
+                eta: self
+                    .total
+                    .and_then(|total| self.rate.eta(total.saturating_sub(self.received))),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_estimate_smooths_spikes() {
+        let mut rate = RateEstimate::default();
+        rate.update(1000, Duration::from_secs(1));
+        assert_eq!(rate.bytes_per_sec, Some(1000.0));
+
+        rate.update(6000, Duration::from_secs(1));
+        assert_eq!(rate.bytes_per_sec, Some(2000.0));
+        assert_eq!(rate.eta(4000), Some(Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn test_rate_estimate_unknown() {
+        let mut rate = RateEstimate::default();
+        rate.update(1000, Duration::ZERO);
+        assert_eq!(rate.bytes_per_sec, None);
+        assert_eq!(rate.eta(1000), None);
+    }
+}
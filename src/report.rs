@@ -0,0 +1,38 @@
+use azalea::ecs::prelude::*;
+use std::{net::SocketAddr, time::Duration};
+
+/// Details ViaProxy printed while it was starting.
+///
+/// This is inserted as a resource when the plugin is added, and updated after ViaProxy restarts,
+/// whether on its own or through [`ViaController`](crate::ViaController).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Resource)]
+pub struct ViaStartupReport {
+    /// The running ViaProxy version and build, like `3.3.7 (git-ViaProxy-3.3.7:0123abc)`.
+    pub via_proxy_version: Option<String>,
+    /// The ViaBackwards version ViaProxy loaded, which translates to older servers.
+    pub via_backwards_version: Option<String>,
+    /// The ViaRewind version ViaProxy loaded, which translates to 1.8 and older servers.
+    pub via_rewind_version: Option<String>,
+    /// The JVM ViaProxy said it's running on, like `OpenJDK 64-Bit Server VM 21.0.3`.
+    pub java_version: Option<String>,
+    /// The address ViaProxy said it's listening on, with wildcard addresses mapped to loopback.
+    pub bound_address: Option<SocketAddr>,
+    /// How long each phase of the latest start took.
+    pub timings: StartupTimings,
+}
+
+/// How long each phase of starting ViaProxy took, to see what makes a start slow.
+///
+/// Phases that were skipped, like downloading when the jars are cached, are still timed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StartupTimings {
+    /// Running `java -version`.
+    pub java_detection: Duration,
+    /// Downloading the jars and installing the extra plugins.
+    pub download: Duration,
+    /// From spawning the JVM until ViaProxy printed its first line.
+    pub spawn: Duration,
+    /// From ViaProxy's first line until it reached the
+    /// [ready milestone](crate::ViaVersionBuilder::with_ready_milestone).
+    pub ready: Duration,
+}
@@ -0,0 +1,311 @@
+use anyhow::{bail, Context, Result};
+use azalea::{ping::ping_server, protocol::ServerAddress};
+
+/// The versions the bundled ViaProxy can target, oldest first, named the way ViaProxy names them.
+///
+/// Releases since 1.7.2 also carry their protocol version number.
+const SUPPORTED_VERSIONS: &[(&str, Option<i32>)] = &[
+    ("c0.0.15a-1", None),
+    ("c0.0.16a-02", None),
+    ("c0.0.17a-c0.0.18a-02", None),
+    ("c0.0.19a-06", None),
+    ("c0.0.20a-c0.27", None),
+    ("c0.28-c0.30", None),
+    ("a1.0.15", None),
+    ("a1.0.16-a1.0.17_04", None),
+    ("a1.1.0-a1.1.2_01", None),
+    ("a1.2.0-a1.2.1_01", None),
+    ("a1.2.2", None),
+    ("a1.2.3-a1.2.3_04", None),
+    ("a1.2.3_05-a1.2.6", None),
+    ("b1.0-b1.1_01", None),
+    ("b1.1_02-b1.2_02", None),
+    ("b1.3-b1.3_01", None),
+    ("b1.4-b1.4_01", None),
+    ("b1.5-b1.5_02", None),
+    ("b1.6-b1.6.6", None),
+    ("b1.7-b1.7.3", None),
+    ("b1.8-b1.8.1", None),
+    ("1.0.0-1.0.1", None),
+    ("1.1", None),
+    ("1.2.1-1.2.3", None),
+    ("1.2.4-1.2.5", None),
+    ("1.3.1-1.3.2", None),
+    ("1.4.2", None),
+    ("1.4.4-1.4.5", None),
+    ("1.4.6-1.4.7", None),
+    ("1.5-1.5.1", None),
+    ("1.5.2", None),
+    ("1.6.1", None),
+    ("1.6.2", None),
+    ("1.6.4", None),
+    ("1.7.2-1.7.5", Some(4)),
+    ("1.7.6-1.7.10", Some(5)),
+    ("1.8.x", Some(47)),
+    ("1.9", Some(107)),
+    ("1.9.1", Some(108)),
+    ("1.9.2", Some(109)),
+    ("1.9.3-1.9.4", Some(110)),
+    ("1.10.x", Some(210)),
+    ("1.11", Some(315)),
+    ("1.11.1-1.11.2", Some(316)),
+    ("1.12", Some(335)),
+    ("1.12.1", Some(338)),
+    ("1.12.2", Some(340)),
+    ("1.13", Some(393)),
+    ("1.13.1", Some(401)),
+    ("1.13.2", Some(404)),
+    ("1.14", Some(477)),
+    ("1.14.1", Some(480)),
+    ("1.14.2", Some(485)),
+    ("1.14.3", Some(490)),
+    ("1.14.4", Some(498)),
+    ("1.15", Some(573)),
+    ("1.15.1", Some(575)),
+    ("1.15.2", Some(578)),
+    ("1.16", Some(735)),
+    ("1.16.1", Some(736)),
+    ("1.16.2", Some(751)),
+    ("1.16.3", Some(753)),
+    ("1.16.4-1.16.5", Some(754)),
+    ("1.17", Some(755)),
+    ("1.17.1", Some(756)),
+    ("1.18-1.18.1", Some(757)),
+    ("1.18.2", Some(758)),
+    ("1.19", Some(759)),
+    ("1.19.1-1.19.2", Some(760)),
+    ("1.19.3", Some(761)),
+    ("1.19.4", Some(762)),
+    ("1.20-1.20.1", Some(763)),
+    ("1.20.2", Some(764)),
+    ("1.20.3-1.20.4", Some(765)),
+    ("1.20.5-1.20.6", Some(766)),
+    ("1.21-1.21.1", Some(767)),
+    ("1.21.2-1.21.3", Some(768)),
+    ("1.21.4", Some(769)),
+];
+
+/// How ViaBedrock's version names start.
+const BEDROCK_PREFIX: &str = "bedrock";
+
+/// List the Java versions the bundled ViaProxy can target, oldest first.
+///
+/// Bedrock versions, see [`is_bedrock`], aren't listed. This is a table bundled for the ViaProxy
+/// version this crate downloads, so it doesn't need to start or query ViaProxy. Ranges like
+/// `1.20.3-1.20.4` also accept each version they cover.
+#[must_use]
+pub fn supported_versions() -> Vec<&'static str> {
+    SUPPORTED_VERSIONS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Make sure the bundled ViaProxy can target a version, so a typo fails before ViaProxy starts.
+///
+/// Versions inside a range like `1.20.3-1.20.4`, `b1.7-b1.7.3` or `1.8.x` are accepted too.
+/// Bedrock versions aren't checked, since they aren't listed.
+///
+/// # Errors
+/// Will return `Err` listing the supported versions if `mc_version` isn't one of them.
+pub fn check_version(mc_version: &str) -> Result<()> {
+    if is_bedrock(mc_version)
+        || SUPPORTED_VERSIONS
+            .iter()
+            .any(|(name, _)| covers(name, mc_version))
+    {
+        return Ok(());
+    }
+
+    bail!(
+        "ViaProxy can't target {mc_version:?}, use one of: {}",
+        supported_versions().join(", ")
+    );
+}
+
+/// Whether a supported version's name covers `version`.
+fn covers(name: &str, version: &str) -> bool {
+    if name == version {
+        return true;
+    }
+
+    if let Some(minor) = name.strip_suffix(".x") {
+        return version == minor
+            || version
+                .strip_prefix(minor)
+                .and_then(|patch| patch.strip_prefix('.'))
+                .is_some_and(|patch| patch.parse::<u32>().is_ok());
+    }
+
+    /* Older versions use dashes in their names too, so only split where both sides are one era */
+    name.match_indices('-').any(|(i, _)| {
+        match (
+            ordered(&name[..i]),
+            ordered(&name[i + 1..]),
+            ordered(version),
+        ) {
+            (Some(start), Some(end), Some(version)) => {
+                start.0 == end.0 && start < end && start <= version && version <= end
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Split a version like `1.20.3`, `b1.7.3` or `a1.2.3_04` into its era and numbers, so versions
+/// of the same era compare in release order.
+fn ordered(version: &str) -> Option<(u8, Vec<u32>)> {
+    let (era, numbers) = match version.as_bytes().first()? {
+        b'c' => (0, &version[1..]),
+        b'a' => (1, &version[1..]),
+        b'b' => (2, &version[1..]),
+        _ => (3, version),
+    };
+
+    let numbers = numbers
+        .split(['.', '_', '-'])
+        .map(|part| {
+            /* Classic versions end in an `a`, like `c0.0.15a` */
+            let part = if era == 0 {
+                part.strip_suffix('a').unwrap_or(part)
+            } else {
+                part
+            };
+            if part.is_empty() || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            part.parse().ok()
+        })
+        .collect::<Option<_>>()?;
+    Some((era, numbers))
+}
+
+/// Whether a target version is a Bedrock one, like `bedrock_1.21.0` or `Bedrock 1.21.0`.
+#[must_use]
+pub fn is_bedrock(mc_version: &str) -> bool {
+    mc_version
+        .get(..BEDROCK_PREFIX.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(BEDROCK_PREFIX))
+}
+
+/// Name a target version the way ViaProxy's `--target-version` does.
+///
+/// ViaBedrock names its versions like `Bedrock 1.21.0`, so `bedrock_1.21.0` and `bedrock-1.21.0`
+/// are renamed to that. Java versions are kept as they are.
+pub(crate) fn target_version(mc_version: &str) -> String {
+    if !is_bedrock(mc_version) {
+        return mc_version.to_owned();
+    }
+
+    let version = mc_version[BEDROCK_PREFIX.len()..].trim_start_matches([' ', '_', '-']);
+    format!("Bedrock {version}")
+}
+
+/// The version ViaProxy names a release by, from its protocol version number.
+///
+/// Releases older than 1.7.2 don't have one, since they're pinged differently.
+pub(crate) fn version_for_protocol(protocol: i32) -> Option<&'static str> {
+    SUPPORTED_VERSIONS
+        .iter()
+        .find(|(_, version_protocol)| *version_protocol == Some(protocol))
+        .map(|(name, _)| *name)
+}
+
+/// Ping a server and find the version ViaProxy should target to join it.
+///
+/// # Errors
+/// Will return `Err` if the ping fails, or the server's protocol version isn't one the bundled
+/// ViaProxy supports.
+pub async fn detect_version(address: &ServerAddress) -> Result<&'static str> {
+    let status = ping_server(address.clone())
+        .await
+        .with_context(|| format!("Failed to ping {}", address.host))?;
+    let version = status.version;
+
+    version_for_protocol(version.protocol).with_context(|| {
+        format!(
+            "{} runs {} (protocol {}), which ViaProxy doesn't support",
+            address.host, version.name, version.protocol
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supported_versions_are_unique() {
+        let versions = supported_versions();
+        for (i, version) in versions.iter().enumerate() {
+            assert!(
+                !versions[i + 1..].contains(version),
+                "{version} is listed twice"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_version() {
+        for version in [
+            "1.21.4",
+            "1.20.3",
+            "1.20.4",
+            "1.8.9",
+            "1.8",
+            "1.7.10",
+            "b1.7-b1.7.3",
+            "b1.7.3",
+            "b1.7",
+            "b1.1_02",
+            "a1.2.4",
+            "c0.0.18a",
+            "c0.0.15a-1",
+            "c0.29",
+        ] {
+            assert!(
+                check_version(version).is_ok(),
+                "{version} should be supported"
+            );
+        }
+        assert!(check_version("bedrock_1.21.0").is_ok());
+
+        for version in [
+            "1.21.o",
+            "1.20.7",
+            "1.8.x.1",
+            "b1.7.4",
+            "a1.2.3_05a",
+            "c0.31",
+            "b1.9",
+            "",
+        ] {
+            assert!(
+                check_version(version).is_err(),
+                "{version} shouldn't be supported"
+            );
+        }
+    }
+
+    #[test]
+    fn test_target_version() {
+        assert_eq!(target_version("bedrock_1.21.0"), "Bedrock 1.21.0");
+        assert_eq!(target_version("Bedrock 1.21.0"), "Bedrock 1.21.0");
+        assert_eq!(target_version("BEDROCK-1.20.80"), "Bedrock 1.20.80");
+        assert_eq!(target_version("1.8.x"), "1.8.x");
+        assert!(!is_bedrock("b1.7-b1.7.3"));
+    }
+
+    #[test]
+    fn test_version_for_protocol() {
+        assert_eq!(version_for_protocol(47), Some("1.8.x"));
+        assert_eq!(version_for_protocol(765), Some("1.20.3-1.20.4"));
+        assert_eq!(version_for_protocol(-1), None);
+    }
+
+    #[test]
+    fn test_supported_versions_are_ordered() {
+        let protocols = SUPPORTED_VERSIONS
+            .iter()
+            .filter_map(|(_, protocol)| *protocol)
+            .collect::<Vec<_>>();
+        assert!(protocols.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
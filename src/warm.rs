@@ -0,0 +1,122 @@
+use parking_lot::Mutex;
+use std::{
+    ffi::OsString,
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+};
+
+use crate::{
+    builder::WildcardDomainHandling,
+    plugins::PluginSource,
+    process::{SpawnConfig, ViaProxyProcess},
+    proxy::BackendProxy,
+    versions::target_version,
+};
+
+/// The proxy kept warm by [`ViaVersionBuilder::with_warm_reuse`](crate::ViaVersionBuilder::with_warm_reuse).
+static WARM_PROXY: LazyLock<Mutex<Option<(WarmKey, Arc<ViaProxyProcess>)>>> =
+    LazyLock::new(Mutex::default);
+
+/// The settings a warm proxy has to have been started with to be reused.
+///
+/// Hooks and the runtime can't be compared, so they aren't part of it.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct WarmKey {
+    mc_version: String,
+    bind_host: IpAddr,
+    bind_port: Option<u16>,
+    auto_restart: bool,
+    java: PathBuf,
+    jvm_args: Vec<String>,
+    via_proxy_jar: PathBuf,
+    config_file: Option<PathBuf>,
+    log_config: Option<PathBuf>,
+    connect_host: IpAddr,
+    working_dir: PathBuf,
+    auth_method: &'static str,
+    wildcard_domain_handling: WildcardDomainHandling,
+    proxy_online_mode: bool,
+    persistent: bool,
+    via_debug: bool,
+    compression_threshold: Option<i32>,
+    legacy_skin_loading: Option<bool>,
+    extra_args: Vec<String>,
+    log_file: Option<PathBuf>,
+    log_file_max_size: Option<u64>,
+    backend_proxy: Option<String>,
+    envs: Vec<(OsString, OsString)>,
+    clear_env: bool,
+    extra_plugins: Vec<PluginSource>,
+}
+
+impl WarmKey {
+    pub(crate) fn new(
+        config: &SpawnConfig,
+        mc_version: &str,
+        bind_host: IpAddr,
+        backend_proxy: Option<&BackendProxy>,
+        extra_plugins: &[PluginSource],
+    ) -> Self {
+        Self {
+            /* Named like the process names it, so it can be updated from it */
+            mc_version: target_version(mc_version),
+            bind_host,
+            bind_port: config.bind_port,
+            auto_restart: config.auto_restart,
+            java: config.java.clone(),
+            jvm_args: config.jvm_args.clone(),
+            via_proxy_jar: config.via_proxy_jar.clone(),
+            config_file: config.config_file.clone(),
+            log_config: config.log_config.clone(),
+            connect_host: config.connect_host,
+            working_dir: config.working_dir.clone(),
+            auth_method: config.auth_method,
+            wildcard_domain_handling: config.wildcard_domain_handling,
+            proxy_online_mode: config.proxy_online_mode,
+            persistent: config.persistent,
+            via_debug: config.via_debug,
+            compression_threshold: config.compression_threshold,
+            legacy_skin_loading: config.legacy_skin_loading,
+            extra_args: config.extra_args.clone(),
+            log_file: config.log_file.clone(),
+            log_file_max_size: config.log_file_max_size,
+            backend_proxy: backend_proxy.map(BackendProxy::url),
+            envs: config.envs.clone(),
+            clear_env: config.clear_env,
+            extra_plugins: extra_plugins.to_vec(),
+        }
+    }
+}
+
+/// Get the warm proxy if it was started with the same settings.
+pub(crate) fn reuse(key: &WarmKey) -> Option<Arc<ViaProxyProcess>> {
+    let warm = WARM_PROXY.lock();
+    let (warm_key, process) = warm.as_ref()?;
+    (warm_key == key).then(|| process.clone())
+}
+
+/// Whether `process` is the warm proxy, which is kept running until the process exits.
+pub(crate) fn is_warm(process: &ViaProxyProcess) -> bool {
+    WARM_PROXY
+        .lock()
+        .as_ref()
+        .is_some_and(|(_, warm_process)| std::ptr::eq(Arc::as_ptr(warm_process), process))
+}
+
+/// Keep a proxy warm, replacing one that was started with other settings.
+pub(crate) fn keep(key: WarmKey, process: Arc<ViaProxyProcess>) {
+    *WARM_PROXY.lock() = Some((key, process));
+}
+
+/// Update the warm proxy's key after [`ViaController`](crate::ViaController) restarted it with
+/// another version or backend proxy, so it's only reused by builders asking for those.
+pub(crate) fn update(process: &Arc<ViaProxyProcess>) {
+    let mut warm = WARM_PROXY.lock();
+    if let Some((key, warm_process)) = warm.as_mut() {
+        if Arc::ptr_eq(warm_process, process) {
+            key.mc_version = process.mc_version();
+            key.backend_proxy = process.backend_proxy().as_ref().map(BackendProxy::url);
+        }
+    }
+}